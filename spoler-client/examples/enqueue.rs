@@ -0,0 +1,27 @@
+//enqueues a task in a local spoler and follows its status
+//
+//    cargo run -p spoler-client --example enqueue -- localhost:8080
+
+use chrono::{Duration, Utc};
+use spoler_client::{Client, TaskBuilder};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| String::from("localhost:8080"));
+    let mut client = Client::builder(addr).connect().await?;
+
+    let task = TaskBuilder::new("example-task")
+        .eta(Utc::now() + Duration::seconds(1))
+        .payload("hello")
+        .build();
+    let reply = client.enqueue(&task).await?;
+    println!("enqueued {:?} for {:?}", reply.id, reply.eta);
+
+    println!("next task: {:?}", client.peek(None).await?);
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    println!("status: {:?}", client.status("example-task").await?);
+    println!("stats: {:?}", client.stats().await?);
+    Ok(())
+}
@@ -0,0 +1,481 @@
+use crate::error::ClientError;
+use crate::tls::TlsOptions;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use spoler_proto::{
+    AckReply, AuthReply, BatchReply, CancelReply, Command, CreateQueueReply, DeleteQueueReply,
+    HeartbeatReply, LeaseReply, LeasedTask, ListReply, PeekReply, QueueConfig, QueueStats, Reply,
+    ReplyStatus, RescheduleReply, StatsReply, StatusReply, Task, TaskChanges, TaskStatus,
+};
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf,
+    WriteHalf,
+};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::ServerName;
+use tokio_rustls::TlsConnector;
+
+//how to reach the server, and how hard to try
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    addr: String,
+    token: Option<String>,
+    connect_timeout: Duration,
+    reconnect_attempts: u32,
+    reconnect_delay: Duration,
+    tls: Option<TlsOptions>,
+}
+
+impl ClientBuilder {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            token: None,
+            connect_timeout: Duration::from_secs(5),
+            reconnect_attempts: 3,
+            reconnect_delay: Duration::from_millis(500),
+            tls: None,
+        }
+    }
+
+    //the token sent on every new connection, for servers running with --auth-file
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    //how many times a lost connection is opened again before giving up,
+    //the delay between attempts doubles every time
+    pub fn reconnect_attempts(mut self, attempts: u32) -> Self {
+        self.reconnect_attempts = attempts;
+        self
+    }
+
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_delay = delay;
+        self
+    }
+
+    //connects with tls, for servers running with --tls-cert
+    pub fn tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    pub async fn connect(self) -> Result<Client, ClientError> {
+        let tls = match &self.tls {
+            Some(tls) => Some(tls.connector(&self.addr)?),
+            None => None,
+        };
+        let mut client = Client {
+            config: self,
+            tls,
+            connection: None,
+        };
+        client.connection().await?;
+        Ok(client)
+    }
+}
+
+//the tcp stream, or the tls stream over it
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<S: AsyncRead + AsyncWrite + Send + Unpin> Stream for S {}
+
+struct Connection {
+    reader: BufReader<ReadHalf<Box<dyn Stream>>>,
+    writer: WriteHalf<Box<dyn Stream>>,
+}
+
+//a connection to spoler speaking json lines.
+//
+//a broken connection is opened again on the next call. the queries and cancel are
+//retried on the new connection, the enqueues are not, as the server may have taken them
+pub struct Client {
+    config: ClientBuilder,
+    tls: Option<(TlsConnector, ServerName)>,
+    connection: Option<Connection>,
+}
+
+impl Client {
+    pub fn builder(addr: impl Into<String>) -> ClientBuilder {
+        ClientBuilder::new(addr)
+    }
+
+    pub async fn connect(addr: impl Into<String>) -> Result<Self, ClientError> {
+        ClientBuilder::new(addr).connect().await
+    }
+
+    //inserts the task in its queue, the reply has the eta resolved by the server
+    pub async fn enqueue(&mut self, task: &Task) -> Result<Reply, ClientError> {
+        self.request(task, false).await
+    }
+
+    //inserts all the tasks or none of them, a rejected batch tells why in each task
+    pub async fn enqueue_batch(&mut self, tasks: &[Task]) -> Result<BatchReply, ClientError> {
+        let tasks = tasks
+            .iter()
+            .map(|task| serde_json::to_value(task).expect("Tasks are always serializable"))
+            .collect();
+        self.request(&Command::Batch { tasks }, false).await
+    }
+
+    //removes the pending task, returns how many were removed
+    pub async fn cancel(&mut self, id: &str) -> Result<usize, ClientError> {
+        let command = Command::Cancel { id: id.to_string() };
+        let reply: CancelReply = self.request(&command, true).await?;
+        Ok(reply.removed)
+    }
+
+    //changes the eta, the repeat interval or the payload of the pending task,
+    //returns how many pending tasks were changed
+    pub async fn reschedule(
+        &mut self,
+        id: &str,
+        changes: TaskChanges,
+    ) -> Result<usize, ClientError> {
+        let command = Command::Reschedule {
+            id: id.to_string(),
+            changes,
+        };
+        let reply: RescheduleReply = self.request(&command, true).await?;
+        Ok(reply.updated)
+    }
+
+    //dispatches the pending task now, a recurring task keeps its schedule.
+    //returns how many tasks were triggered
+    pub async fn run_now(&mut self, id: &str) -> Result<usize, ClientError> {
+        let command = Command::RunNow { id: id.to_string() };
+        let reply: RescheduleReply = self.request(&command, false).await?;
+        Ok(reply.updated)
+    }
+
+    //none when the server never saw a task with that id
+    pub async fn status(&mut self, id: &str) -> Result<Option<TaskStatus>, ClientError> {
+        let command = Command::Status { id: id.to_string() };
+        let reply: StatusReply = self.request(&command, true).await?;
+        Ok(reply.task)
+    }
+
+    pub async fn stats(&mut self) -> Result<Vec<QueueStats>, ClientError> {
+        let reply: StatsReply = self.request(&Command::Stats, true).await?;
+        Ok(reply.queues)
+    }
+
+    //a page of the pending tasks of a queue, sorted by eta
+    pub async fn list(
+        &mut self,
+        queue: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<ListReply, ClientError> {
+        let command = Command::List {
+            queue: queue.to_string(),
+            offset,
+            limit,
+        };
+        self.request(&command, true).await
+    }
+
+    //the next task that is going to be due, in one queue or in all of them
+    pub async fn peek(&mut self, queue: Option<&str>) -> Result<Option<Task>, ClientError> {
+        let command = Command::Peek {
+            queue: queue.map(String::from),
+        };
+        let reply: PeekReply = self.request(&command, true).await?;
+        Ok(reply.task)
+    }
+
+    //creates an empty queue, the values missing from the config are the defaults of the server
+    pub async fn create_queue(
+        &mut self,
+        name: &str,
+        config: QueueConfig,
+    ) -> Result<CreateQueueReply, ClientError> {
+        let command = Command::CreateQueue {
+            name: name.to_string(),
+            config,
+        };
+        self.request(&command, false).await
+    }
+
+    //deletes a queue, with force its pending tasks are cancelled, otherwise it must be empty.
+    //returns how many tasks were cancelled
+    pub async fn delete_queue(&mut self, name: &str, force: bool) -> Result<usize, ClientError> {
+        let command = Command::DeleteQueue {
+            name: name.to_string(),
+            force,
+        };
+        let reply: DeleteQueueReply = self.request(&command, false).await?;
+        Ok(reply.removed)
+    }
+
+    //takes up to max due tasks of the queues, they go back to their queue if they are
+    //not acked or nacked before the visibility timeout
+    pub async fn lease(
+        &mut self,
+        queues: &[String],
+        max: usize,
+        visibility_timeout: u32,
+    ) -> Result<Vec<LeasedTask>, ClientError> {
+        let command = Command::Lease {
+            queues: queues.to_vec(),
+            max,
+            visibility_timeout,
+        };
+        let reply: LeaseReply = self.request(&command, false).await?;
+        Ok(reply.leases)
+    }
+
+    //the leased task finished, false when the lease had already expired
+    pub async fn ack(&mut self, lease_id: &str) -> Result<bool, ClientError> {
+        let command = Command::Ack {
+            lease_id: lease_id.to_string(),
+        };
+        let reply: AckReply = self.request(&command, true).await?;
+        Ok(reply.found)
+    }
+
+    //the leased task failed, with requeue it runs again after the delay (in seconds)
+    pub async fn nack(
+        &mut self,
+        lease_id: &str,
+        error: Option<String>,
+        requeue: bool,
+        delay: u32,
+    ) -> Result<bool, ClientError> {
+        let command = Command::Nack {
+            lease_id: lease_id.to_string(),
+            error,
+            requeue,
+            delay,
+        };
+        let reply: AckReply = self.request(&command, true).await?;
+        Ok(reply.found)
+    }
+
+    //the leased tasks are still running, extends their visibility timeout
+    pub async fn heartbeat(
+        &mut self,
+        lease_ids: Vec<String>,
+        visibility_timeout: u32,
+    ) -> Result<HeartbeatReply, ClientError> {
+        let command = Command::Heartbeat {
+            lease_ids,
+            visibility_timeout,
+        };
+        self.request(&command, true).await
+    }
+
+    //sends the message and reads its reply, on a broken connection it's sent again
+    //on a new one if retry is true
+    async fn request<M, R>(&mut self, message: &M, retry: bool) -> Result<R, ClientError>
+    where
+        M: Serialize,
+        R: DeserializeOwned,
+    {
+        let mut line = serde_json::to_string(message).expect("Messages are always serializable");
+        line.push('\n');
+
+        let mut attempts = 0;
+        let value = loop {
+            match self.send(&line).await {
+                Ok(value) => break value,
+                Err(e) if e.is_connection() => {
+                    self.connection = None;
+                    if !retry || attempts >= self.config.reconnect_attempts {
+                        return Err(e);
+                    }
+                    attempts += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        parse_reply(value)
+    }
+
+    async fn send(&mut self, line: &str) -> Result<Value, ClientError> {
+        let connection = self.connection().await?;
+        connection.writer.write_all(line.as_bytes()).await?;
+        read_line(&mut connection.reader).await
+    }
+
+    //the open connection, or a new one
+    async fn connection(&mut self) -> Result<&mut Connection, ClientError> {
+        if self.connection.is_none() {
+            self.connection = Some(self.reconnect().await?);
+        }
+        Ok(self.connection.as_mut().unwrap())
+    }
+
+    async fn reconnect(&self) -> Result<Connection, ClientError> {
+        let mut delay = self.config.reconnect_delay;
+        let mut attempts = 0;
+        loop {
+            match self.open().await {
+                Err(e) if e.is_connection() && attempts < self.config.reconnect_attempts => {
+                    attempts += 1;
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    //connects and authenticates
+    async fn open(&self) -> Result<Connection, ClientError> {
+        let stream = tokio::time::timeout(
+            self.config.connect_timeout,
+            TcpStream::connect(&self.config.addr),
+        )
+        .await
+        .map_err(|_| {
+            ClientError::Io(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("Timed out connecting to {}", self.config.addr),
+            ))
+        })??;
+        let stream: Box<dyn Stream> = match &self.tls {
+            //the failed handshakes are not retried, the certificates won't change
+            Some((connector, server_name)) => Box::new(
+                connector
+                    .connect(server_name.clone(), stream)
+                    .await
+                    .map_err(|e| ClientError::Tls(format!("Tls handshake failed: {}", e)))?,
+            ),
+            None => Box::new(stream),
+        };
+        let (read, write) = tokio::io::split(stream);
+        let mut connection = Connection {
+            reader: BufReader::new(read),
+            writer: write,
+        };
+
+        if let Some(token) = &self.config.token {
+            let mut line = serde_json::to_string(&Command::Auth {
+                token: token.clone(),
+            })
+            .expect("Messages are always serializable");
+            line.push('\n');
+            connection.writer.write_all(line.as_bytes()).await?;
+            let reply: AuthReply = parse_reply(read_line(&mut connection.reader).await?)?;
+            if reply.status != ReplyStatus::Accepted {
+                return Err(ClientError::Unauthorized);
+            }
+        }
+        Ok(connection)
+    }
+}
+
+async fn read_line<R>(reader: &mut R) -> Result<Value, ClientError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Err(ClientError::Disconnected);
+    }
+    serde_json::from_str(&line).map_err(|e| ClientError::Protocol(e.to_string()))
+}
+
+//the rejected tasks and the failed commands come back with a reason, they are errors.
+//a rejected batch is not, its reply tells why each task was rejected
+fn parse_reply<R: DeserializeOwned>(value: Value) -> Result<R, ClientError> {
+    let rejected = value.get("status").and_then(Value::as_str) == Some("rejected");
+    if let (true, Some(reason)) = (rejected, value.get("reason").and_then(Value::as_str)) {
+        return Err(ClientError::Rejected(reason.to_string()));
+    }
+    serde_json::from_value(value).map_err(|e| ClientError::Protocol(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TaskBuilder;
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    //a server that answers each connection with its replies, one per line read, and closes it.
+    //it gives back the lines it read
+    async fn server(connections: Vec<Vec<&'static str>>) -> (String, JoinHandle<Vec<Value>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = tokio::spawn(async move {
+            let mut received = Vec::new();
+            for replies in connections {
+                let (socket, _) = listener.accept().await.unwrap();
+                let (read, mut write) = socket.into_split();
+                let mut reader = BufReader::new(read);
+                for reply in replies {
+                    received.push(read_line(&mut reader).await.unwrap());
+                    write
+                        .write_all(format!("{}\n", reply).as_bytes())
+                        .await
+                        .unwrap();
+                }
+            }
+            received
+        });
+        (addr, handle)
+    }
+
+    #[tokio::test]
+    async fn enqueues_and_reads_the_reply() {
+        let (addr, server) = server(vec![vec![
+            r#"{"id":"a","queue":"mail","eta":"2030-01-01T00:00:00+00:00","status":"accepted","reason":null}"#,
+            r#"{"command":"status","id":"b","task":null}"#,
+        ]])
+        .await;
+        let mut client = Client::connect(addr).await.unwrap();
+
+        let task = TaskBuilder::new("a").queue("mail").payload("hi").build();
+        let reply = client.enqueue(&task).await.unwrap();
+        assert_eq!(reply.status, ReplyStatus::Accepted);
+        assert_eq!(reply.eta.as_deref(), Some("2030-01-01T00:00:00+00:00"));
+        assert!(client.status("b").await.unwrap().is_none());
+
+        let received = server.await.unwrap();
+        assert_eq!(received[0]["id"], "a");
+        assert_eq!(received[0]["queue"], "mail");
+        assert_eq!(received[0]["payload"], "hi");
+        assert_eq!(received[1]["command"], "status");
+        assert_eq!(received[1]["id"], "b");
+    }
+
+    #[tokio::test]
+    async fn rejected_tasks_are_errors() {
+        let (addr, _) = server(vec![vec![
+            r#"{"id":"a","queue":"0","eta":null,"status":"rejected","reason":"Queue full"}"#,
+        ]])
+        .await;
+        let mut client = Client::connect(addr).await.unwrap();
+        match client.enqueue(&TaskBuilder::new("a").build()).await {
+            Err(ClientError::Rejected(reason)) => assert_eq!(reason, "Queue full"),
+            other => panic!("unexpected reply {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn queries_are_sent_again_on_a_new_connection() {
+        let (addr, server) = server(vec![
+            vec![],
+            vec![r#"{"command":"cancel","id":"a","removed":1}"#],
+        ])
+        .await;
+        let mut client = Client::builder(addr)
+            .reconnect_delay(Duration::from_millis(10))
+            .connect()
+            .await
+            .unwrap();
+        assert_eq!(client.cancel("a").await.unwrap(), 1);
+        assert_eq!(server.await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+use std::io;
+
+//everything that can go wrong talking to spoler
+#[derive(Debug)]
+pub enum ClientError {
+    //connecting, reading or writing failed
+    Io(io::Error),
+    //the server closed the connection
+    Disconnected,
+    //the server answered something that is not the expected reply
+    Protocol(String),
+    //the server did not accept the token
+    Unauthorized,
+    //the server refused the task or the command, with its reason
+    Rejected(String),
+    //the tls certificates could not be loaded, or the handshake failed
+    Tls(String),
+}
+
+impl ClientError {
+    //the connection is broken, the next call reconnects
+    pub fn is_connection(&self) -> bool {
+        matches!(self, ClientError::Io(_) | ClientError::Disconnected)
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "Connection error: {}", e),
+            ClientError::Disconnected => write!(f, "The server closed the connection"),
+            ClientError::Protocol(reason) => write!(f, "Unexpected reply: {}", reason),
+            ClientError::Unauthorized => write!(f, "The token was not accepted"),
+            ClientError::Rejected(reason) => write!(f, "Rejected: {}", reason),
+            ClientError::Tls(reason) => write!(f, "Tls error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
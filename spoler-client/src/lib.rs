@@ -0,0 +1,15 @@
+//async client for spoler, it talks the same json lines as the tcp server
+//with the types of spoler-proto, so both sides can't drift apart
+
+mod client;
+mod error;
+mod tls;
+
+pub use client::{Client, ClientBuilder};
+pub use error::ClientError;
+pub use spoler_proto::{
+    BatchReply, CreateQueueReply, HeartbeatReply, LeasedTask, ListReply, Overflow, QueueConfig,
+    QueueCounters, QueueStats, Reply, ReplyStatus, Task, TaskBuilder, TaskChanges, TaskSettings,
+    TaskState, TaskStatus, TaskType,
+};
+pub use tls::TlsOptions;
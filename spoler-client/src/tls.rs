@@ -0,0 +1,88 @@
+use crate::error::ClientError;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio_rustls::rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+//the certificates of a tls connection to a server running with --tls-cert
+#[derive(Debug, Clone)]
+pub struct TlsOptions {
+    //the ca that signed the certificate of the server
+    pub ca: String,
+    //the certificate and key of the client, for servers running with --tls-client-ca
+    pub identity: Option<(String, String)>,
+    //the name the certificate of the server is checked against, and sent as sni.
+    //the host of the address without it
+    pub server_name: Option<String>,
+}
+
+impl TlsOptions {
+    //the connector of the handshakes, and the name of the server
+    pub(crate) fn connector(&self, addr: &str) -> Result<(TlsConnector, ServerName), ClientError> {
+        let mut roots = RootCertStore::empty();
+        for ca in load_certs(&self.ca)? {
+            roots
+                .add(&ca)
+                .map_err(|e| ClientError::Tls(format!("Invalid ca certificate: {}", e)))?;
+        }
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let config = match &self.identity {
+            Some((cert, key)) => builder
+                .with_single_cert(load_certs(cert)?, load_key(key)?)
+                .map_err(|e| {
+                    ClientError::Tls(format!("Invalid client certificate or key: {}", e))
+                })?,
+            None => builder.with_no_client_auth(),
+        };
+
+        let name = match &self.server_name {
+            Some(name) => name.as_str(),
+            None => host(addr),
+        };
+        let server_name = ServerName::try_from(name)
+            .map_err(|_| ClientError::Tls(format!("Invalid server name {}", name)))?;
+        Ok((TlsConnector::from(Arc::new(config)), server_name))
+    }
+}
+
+//the host of a host:port address, without the brackets of ipv6
+fn host(addr: &str) -> &str {
+    let host = match addr.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host,
+        _ => addr,
+    };
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>, ClientError> {
+    let file = File::open(path)
+        .map_err(|e| ClientError::Tls(format!("Failed to open {}: {}", path, e)))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|e| ClientError::Tls(format!("Invalid tls certificate {}: {}", path, e)))?;
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_key(path: &str) -> Result<PrivateKey, ClientError> {
+    let file = File::open(path)
+        .map_err(|e| ClientError::Tls(format!("Failed to open {}: {}", path, e)))?;
+    let mut reader = BufReader::new(file);
+    loop {
+        match rustls_pemfile::read_one(&mut reader)
+            .map_err(|e| ClientError::Tls(format!("Invalid tls key {}: {}", path, e)))?
+        {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => {
+                return Err(ClientError::Tls(format!(
+                    "No private key found in {}",
+                    path
+                )))
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::task::{Task, TaskSettings, TaskType};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::collections::BTreeMap;
+
+//builds the tasks sent to spoler, so the settings always match the type of the task
+//
+//    let task = TaskBuilder::new("send-report")
+//        .queue("reports")
+//        .eta(Utc::now() + Duration::minutes(5))
+//        .request("POST", "https://example.com/reports")
+//        .header("content-type", "application/json")
+//        .payload(r#"{"report": 42}"#)
+//        .build();
+#[derive(Debug, Clone)]
+pub struct TaskBuilder {
+    id: String,
+    queue: String,
+    eta: Option<String>,
+    task_type: TaskType,
+    payload: Option<String>,
+    settings: TaskSettings,
+    headers: BTreeMap<String, String>,
+    priority: i32,
+    timezone: Option<String>,
+    idempotency_key: Option<String>,
+    upsert: bool,
+}
+
+impl TaskBuilder {
+    //a task of the queue "0", the first one of a node started with --queues, due as soon as it's enqueued
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            queue: String::from("0"),
+            eta: None,
+            task_type: TaskType::Other,
+            payload: None,
+            settings: TaskSettings {
+                repeat_interval: None,
+                cron: None,
+                retries: None,
+                url: None,
+                headers: None,
+                method: None,
+                executor_ref: None,
+            },
+            headers: BTreeMap::new(),
+            priority: 0,
+            timezone: None,
+            idempotency_key: None,
+            upsert: false,
+        }
+    }
+
+    pub fn queue(mut self, queue: impl Into<String>) -> Self {
+        self.queue = queue.into();
+        self
+    }
+
+    pub fn eta(mut self, eta: DateTime<Utc>) -> Self {
+        self.eta = Some(eta.to_rfc3339());
+        self
+    }
+
+    //an eta in the local time of the time zone of the task
+    pub fn local_eta(mut self, eta: NaiveDateTime) -> Self {
+        self.eta = Some(eta.format("%Y-%m-%dT%H:%M:%S%.f").to_string());
+        self
+    }
+
+    //the iana time zone of the task, like Europe/Madrid. its local etas, repetitions and cron
+    //times are in that zone, so a daily task keeps its hour when the clocks change
+    pub fn timezone(mut self, timezone: impl Into<String>) -> Self {
+        self.timezone = Some(timezone.into());
+        self
+    }
+
+    pub fn payload(mut self, payload: impl Into<String>) -> Self {
+        self.payload = Some(payload.into());
+        self
+    }
+
+    //the worker resolves the task calling this url
+    pub fn request(mut self, method: impl Into<String>, url: impl Into<String>) -> Self {
+        self.task_type = TaskType::Api;
+        self.settings.method = Some(method.into());
+        self.settings.url = Some(url.into());
+        self
+    }
+
+    //a header of the request
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    //the python worker resolves the task calling this method of the app
+    pub fn python(mut self, executor_ref: impl Into<String>) -> Self {
+        self.task_type = TaskType::Python;
+        self.settings.executor_ref = Some(executor_ref.into());
+        self
+    }
+
+    //the task runs again every interval, it needs an eta to count from
+    pub fn repeat_every(mut self, seconds: u32) -> Self {
+        self.settings.repeat_interval = Some(seconds);
+        self
+    }
+
+    //the task runs at the times of the cron expression, like "0 9 * * 1-5" for the weekdays at 9:00.
+    //without an eta it's first due at the next of them
+    pub fn cron(mut self, expression: impl Into<String>) -> Self {
+        self.settings.cron = Some(expression.into());
+        self
+    }
+
+    //how many times a recurring task repeats, it repeats forever without it
+    pub fn repetitions(mut self, repetitions: i32) -> Self {
+        self.settings.retries = Some(repetitions);
+        self
+    }
+
+    //among the tasks due at the same time, the ones with the highest priority run first
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    //the retries of the enqueue carry the same key, spoler inserts the task once.
+    //the id is the key without it
+    pub fn idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    //the task replaces the one enqueued before with the same key, if it's still pending
+    pub fn upsert(mut self) -> Self {
+        self.upsert = true;
+        self
+    }
+
+    pub fn build(self) -> Task {
+        let mut settings = self.settings;
+        if !self.headers.is_empty() {
+            settings.headers = Some(
+                serde_json::to_string(&self.headers).expect("Headers are always serializable"),
+            );
+        }
+        let has_settings = settings.repeat_interval.is_some()
+            || settings.cron.is_some()
+            || settings.retries.is_some()
+            || settings.url.is_some()
+            || settings.executor_ref.is_some()
+            || settings.headers.is_some();
+
+        Task {
+            queue: self.queue,
+            id: self.id,
+            eta: self.eta,
+            task_type: self.task_type as i32,
+            payload: self.payload,
+            settings: has_settings.then_some(settings),
+            priority: self.priority,
+            timezone: self.timezone,
+            idempotency_key: self.idempotency_key,
+            upsert: self.upsert,
+            seq: 0,
+            eta_at: None,
+        }
+    }
+}
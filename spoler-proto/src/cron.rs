@@ -0,0 +1,304 @@
+use crate::timezone::from_local;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Utc};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+//how far the next occurrence is looked for, enough for the 29th of february of any year
+const MAX_YEARS: i32 = 10;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAYS_OF_WEEK: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+//a cron expression, in the standard syntax:
+//
+//    minute hour day-of-month month day-of-week
+//    second minute hour day-of-month month day-of-week
+//
+//fields take *, ?, lists (1,15), ranges (1-5), steps (*/10, 8-18/2) and the names of the
+//months and days (jan, mon). sunday is 0 or 7. when both days are restricted, either matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    seconds: u64,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    //the days fields that are not *, they decide how the days match
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = raw.split_whitespace().collect();
+        //the 5 fields syntax runs at the second 0
+        let (seconds, fields) = match fields.len() {
+            5 => ("0", &fields[..]),
+            6 => (fields[0], &fields[1..]),
+            _ => {
+                return Err(format!(
+                    "Invalid cron expression {}, it needs 5 or 6 fields",
+                    raw
+                ))
+            }
+        };
+        let invalid = |e: String| format!("Invalid cron expression {}: {}", raw, e);
+
+        let days_of_week = parse_field(fields[4], 0, 7, &DAYS_OF_WEEK).map_err(invalid)?;
+        Ok(Self {
+            seconds: parse_field(seconds, 0, 59, &[]).map_err(invalid)?,
+            minutes: parse_field(fields[0], 0, 59, &[]).map_err(invalid)?,
+            hours: parse_field(fields[1], 0, 23, &[]).map_err(invalid)?,
+            days_of_month: parse_field(fields[2], 1, 31, &[]).map_err(invalid)?,
+            //the names of the months start at 1
+            months: parse_field(fields[3], 1, 12, &MONTHS).map_err(invalid)?,
+            //7 is sunday too
+            days_of_week: (days_of_week | days_of_week >> 7) & 0x7f,
+            days_of_month_restricted: !is_any(fields[2]),
+            days_of_week_restricted: !is_any(fields[4]),
+        })
+    }
+}
+
+impl CronSchedule {
+    //the first time of the schedule strictly after the given one, none when it never happens
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let limit = after.year() + MAX_YEARS;
+        let mut time = after.with_nanosecond(0)? + Duration::seconds(1);
+        while time.year() <= limit {
+            if !contains(self.months, time.month()) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.matches_day(time.date()) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !contains(self.hours, time.hour()) {
+                time = time.with_minute(0)?.with_second(0)? + Duration::hours(1);
+            } else if !contains(self.minutes, time.minute()) {
+                time = time.with_second(0)? + Duration::minutes(1);
+            } else if !contains(self.seconds, time.second()) {
+                time += Duration::seconds(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    //the first time of the schedule in the local time of the zone strictly after the instant.
+    //the times that dst changes skip or repeat are resolved as from_local does, so they run once
+    pub fn next_in(&self, after: DateTime<Utc>, zone: &Tz) -> Option<DateTime<Utc>> {
+        let mut local = after.with_timezone(zone).naive_local();
+        loop {
+            local = self.next_after(local)?;
+            let next = from_local(zone, local).with_timezone(&Utc);
+            //the times of a repeated hour that already happened, and the skipped
+            //times that were moved to the instant already used
+            if next > after {
+                return Some(next);
+            }
+        }
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day_of_month = contains(self.days_of_month, date.day());
+        let day_of_week = contains(self.days_of_week, date.weekday().num_days_from_sunday());
+        match (self.days_of_month_restricted, self.days_of_week_restricted) {
+            (true, true) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+}
+
+fn contains(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+fn is_any(field: &str) -> bool {
+    field == "*" || field == "?"
+}
+
+//the values of a field as a bit set, names[0] is the value min
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("invalid step {}", step)),
+            },
+            None => (part, 1),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if is_any(range) => (min, max),
+            Some((first, last)) => (
+                parse_value(first, min, max, names)?,
+                parse_value(last, min, max, names)?,
+            ),
+            //a single value with a step runs from it to the end
+            None if step > 1 => (parse_value(range, min, max, names)?, max),
+            None => {
+                let value = parse_value(range, min, max, names)?;
+                (value, value)
+            }
+        };
+        if first > last {
+            return Err(format!("invalid range {}", range));
+        }
+        for value in (first..=last).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+fn parse_value(raw: &str, min: u32, max: u32, names: &[&str]) -> Result<u32, String> {
+    let value = match names.iter().position(|name| name.eq_ignore_ascii_case(raw)) {
+        Some(position) => min + position as u32,
+        None => raw
+            .parse::<u32>()
+            .map_err(|_| format!("invalid value {}", raw))?,
+    };
+    if value < min || value > max {
+        return Err(format!("{} is out of {}-{}", value, min, max));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(raw: &str) -> NaiveDateTime {
+        raw.parse().unwrap()
+    }
+
+    fn next(cron: &str, after: &str) -> Option<NaiveDateTime> {
+        cron.parse::<CronSchedule>().unwrap().next_after(at(after))
+    }
+
+    fn next_in_madrid(cron: &str, after: &str) -> String {
+        let after = after.parse::<DateTime<Utc>>().unwrap();
+        let schedule = cron.parse::<CronSchedule>().unwrap();
+        let next = schedule.next_in(after, &chrono_tz::Europe::Madrid).unwrap();
+        next.to_rfc3339()
+    }
+
+    #[test]
+    fn parses_the_field_syntax() {
+        assert_eq!(
+            next("*/15 9-17 * * mon-fri", "2026-11-06T17:50:00"),
+            Some(at("2026-11-09T09:00:00"))
+        );
+        assert_eq!(
+            next("0 8,20 1 jan,jul ?", "2026-07-01T08:00:00"),
+            Some(at("2026-07-01T20:00:00"))
+        );
+        //a single value with a step runs to the end of the field
+        assert_eq!(
+            next("50/5 * * * *", "2026-11-01T10:55:00"),
+            Some(at("2026-11-01T11:50:00"))
+        );
+        //the 6 fields syntax starts with the seconds
+        assert_eq!(
+            next("30 0 12 * * *", "2026-11-01T12:00:30"),
+            Some(at("2026-11-02T12:00:30"))
+        );
+        //sunday is 0 and 7
+        assert_eq!(
+            "0 0 * * 0".parse::<CronSchedule>(),
+            "0 0 * * 7".parse::<CronSchedule>()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for cron in [
+            "0 0 L * *",
+            "*/0 * * * *",
+            "0 0 * * 8",
+            "60 * * * *",
+            "0 0 0 * *",
+            "0 0 * 13 *",
+            "0 5-1 * * *",
+            "0 0 * * mon-",
+            "* * * *",
+            "* * * * * * *",
+        ] {
+            assert!(cron.parse::<CronSchedule>().is_err(), "{}", cron);
+        }
+    }
+
+    #[test]
+    fn days_that_never_happen_never_match() {
+        assert_eq!(next("0 0 31 2 *", "2026-01-01T00:00:00"), None);
+        assert_eq!(next("0 0 31 4,6,9,11 *", "2026-01-01T00:00:00"), None);
+        assert_eq!(
+            next("0 0 29 2 *", "2026-01-01T00:00:00"),
+            Some(at("2028-02-29T00:00:00"))
+        );
+    }
+
+    #[test]
+    fn restricted_days_of_month_and_of_week_match_either() {
+        //the 10th or any monday
+        assert_eq!(
+            next("0 0 10 * 1", "2026-11-01T00:00:00"),
+            Some(at("2026-11-02T00:00:00"))
+        );
+        assert_eq!(
+            next("0 0 10 * 1", "2026-11-09T00:00:00"),
+            Some(at("2026-11-10T00:00:00"))
+        );
+        //a * in one of them leaves the other one alone
+        assert_eq!(
+            next("0 0 10 * *", "2026-11-01T00:00:00"),
+            Some(at("2026-11-10T00:00:00"))
+        );
+        assert_eq!(
+            next("0 0 * * 1", "2026-11-09T00:00:00"),
+            Some(at("2026-11-16T00:00:00"))
+        );
+    }
+
+    #[test]
+    fn the_times_skipped_by_dst_run_after_the_gap() {
+        //madrid jumps from 02:00+01:00 to 03:00+02:00 the 29th of march of 2026
+        assert_eq!(
+            next_in_madrid("30 2 * * *", "2026-03-28T01:30:00Z"),
+            "2026-03-29T01:30:00+00:00"
+        );
+        assert_eq!(
+            next_in_madrid("30 2 * * *", "2026-03-29T01:30:00Z"),
+            "2026-03-30T00:30:00+00:00"
+        );
+        assert_eq!(
+            next_in_madrid("0 * * * *", "2026-03-29T00:30:00Z"),
+            "2026-03-29T01:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn the_times_repeated_by_dst_run_once() {
+        //madrid goes back from 03:00+02:00 to 02:00+01:00 the 25th of october of 2026
+        assert_eq!(
+            next_in_madrid("30 2 * * *", "2026-10-24T23:00:00Z"),
+            "2026-10-25T00:30:00+00:00"
+        );
+        assert_eq!(
+            next_in_madrid("30 2 * * *", "2026-10-25T00:30:00Z"),
+            "2026-10-26T01:30:00+00:00"
+        );
+        assert_eq!(
+            next_in_madrid("*/30 * * * *", "2026-10-25T00:30:00Z"),
+            "2026-10-25T02:00:00+00:00"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use crate::protocol::{Message, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+//the biggest frame we accept, so a broken length prefix can't make us allocate forever
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+//how the messages are delimited and encoded in a connection
+//every connection starts with json lines, and can switch with the framing command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Framing {
+    //one json message per line
+    Json,
+    //a 4 bytes big endian length, followed by a messagepack message of that length
+    Msgpack,
+}
+
+impl Framing {
+    //takes the next complete frame out of the buffer, if it's there
+    pub fn next_frame(&self, buffer: &mut Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+        match self {
+            Framing::Json => match buffer.iter().position(|b| *b == b'\n') {
+                Some(end) if end <= MAX_FRAME_LEN => {
+                    let mut frame: Vec<u8> = buffer.drain(..=end).collect();
+                    frame.pop();
+                    Ok(Some(frame))
+                }
+                None if buffer.len() <= MAX_FRAME_LEN => Ok(None),
+                //a line without end can't make us allocate forever either
+                _ => Err(format!(
+                    "Line of more than {} bytes is too big",
+                    MAX_FRAME_LEN
+                )),
+            },
+            Framing::Msgpack => {
+                if buffer.len() < 4 {
+                    return Ok(None);
+                }
+                let len = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
+                if len > MAX_FRAME_LEN {
+                    return Err(format!("Frame of {} bytes is too big", len));
+                }
+                if buffer.len() < 4 + len {
+                    return Ok(None);
+                }
+                Ok(Some(buffer.drain(..4 + len).skip(4).collect()))
+            }
+        }
+    }
+
+    pub fn decode(&self, frame: &[u8]) -> Result<Message, String> {
+        match self {
+            Framing::Json => {
+                let raw = std::str::from_utf8(frame).map_err(|e| format!("Invalid utf8: {}", e))?;
+                raw.trim().parse()
+            }
+            Framing::Msgpack => {
+                let value: Value = rmp_serde::from_slice(frame)
+                    .map_err(|e| format!("Invalid messagepack: {}", e))?;
+                Message::from_value(value)
+            }
+        }
+    }
+
+    pub fn encode(&self, response: &Response) -> Vec<u8> {
+        match self {
+            Framing::Json => response.to_line().into_bytes(),
+            Framing::Msgpack => {
+                let message = self.encode_message(response);
+                let mut frame = (message.len() as u32).to_be_bytes().to_vec();
+                frame.extend(message);
+                frame
+            }
+        }
+    }
+
+    //the response without delimiter nor length, for transports that frame the messages themselves
+    pub fn encode_message(&self, response: &Response) -> Vec<u8> {
+        match self {
+            Framing::Json => {
+                serde_json::to_vec(response).expect("Responses are always serializable")
+            }
+            Framing::Msgpack => {
+                rmp_serde::to_vec_named(response).expect("Responses are always serializable")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msgpack_frame(body: &[u8]) -> Vec<u8> {
+        let mut frame = (body.len() as u32).to_be_bytes().to_vec();
+        frame.extend(body);
+        frame
+    }
+
+    #[test]
+    fn json_frames_are_lines() {
+        let mut buffer = b"{\"a\":1}\n{\"b\":2}\n{\"c\"".to_vec();
+        assert_eq!(
+            Framing::Json.next_frame(&mut buffer),
+            Ok(Some(b"{\"a\":1}".to_vec()))
+        );
+        assert_eq!(
+            Framing::Json.next_frame(&mut buffer),
+            Ok(Some(b"{\"b\":2}".to_vec()))
+        );
+        assert_eq!(Framing::Json.next_frame(&mut buffer), Ok(None));
+        assert_eq!(buffer, b"{\"c\"");
+    }
+
+    #[test]
+    fn json_lines_that_are_too_long_are_rejected() {
+        let mut buffer = vec![b' '; MAX_FRAME_LEN];
+        assert_eq!(Framing::Json.next_frame(&mut buffer), Ok(None));
+        buffer.push(b' ');
+        assert!(Framing::Json.next_frame(&mut buffer).is_err());
+        buffer.push(b'\n');
+        assert!(Framing::Json.next_frame(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn msgpack_frames_wait_for_their_length_and_body() {
+        let frame = msgpack_frame(b"hello");
+        for len in 0..frame.len() {
+            let mut buffer = frame[..len].to_vec();
+            assert_eq!(Framing::Msgpack.next_frame(&mut buffer), Ok(None));
+            assert_eq!(buffer, &frame[..len]);
+        }
+    }
+
+    #[test]
+    fn msgpack_frames_in_the_same_read_are_taken_one_by_one() {
+        let mut buffer = msgpack_frame(b"first");
+        buffer.extend(msgpack_frame(b""));
+        buffer.extend(msgpack_frame(b"second"));
+        buffer.extend(&[0, 0]);
+        assert_eq!(
+            Framing::Msgpack.next_frame(&mut buffer),
+            Ok(Some(b"first".to_vec()))
+        );
+        assert_eq!(
+            Framing::Msgpack.next_frame(&mut buffer),
+            Ok(Some(Vec::new()))
+        );
+        assert_eq!(
+            Framing::Msgpack.next_frame(&mut buffer),
+            Ok(Some(b"second".to_vec()))
+        );
+        assert_eq!(Framing::Msgpack.next_frame(&mut buffer), Ok(None));
+        assert_eq!(buffer, [0, 0]);
+    }
+
+    #[test]
+    fn msgpack_frames_that_are_too_big_are_rejected() {
+        let mut buffer = ((MAX_FRAME_LEN + 1) as u32).to_be_bytes().to_vec();
+        assert!(Framing::Msgpack.next_frame(&mut buffer).is_err());
+        let mut buffer = (MAX_FRAME_LEN as u32).to_be_bytes().to_vec();
+        assert_eq!(Framing::Msgpack.next_frame(&mut buffer), Ok(None));
+    }
+}
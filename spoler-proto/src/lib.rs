@@ -0,0 +1,22 @@
+//the messages of the spoler protocol, shared by the server and the clients
+//so both sides read and write the same json
+
+mod builder;
+mod cron;
+mod framing;
+mod protocol;
+mod queue;
+mod status;
+mod task;
+mod timezone;
+
+pub use builder::TaskBuilder;
+pub use cron::CronSchedule;
+pub use framing::Framing;
+pub use protocol::*;
+pub use queue::{
+    optional_queue_name, queue_name, queue_names, validate_queue_name, Overflow, QueueConfig,
+};
+pub use status::{QueueCounters, TaskState, TaskStatus};
+pub use task::{Task, TaskChanges, TaskSettings, TaskType};
+pub use timezone::{from_local, parse_eta, parse_timezone};
@@ -0,0 +1,494 @@
+use crate::framing::Framing;
+use crate::queue::{optional_queue_name, queue_name, queue_names, Overflow, QueueConfig};
+use crate::status::{QueueCounters, TaskStatus};
+use crate::task::{Task, TaskChanges};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::str::FromStr;
+
+//commands are json objects with a "command" key, anything else is read as a task
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    //authenticates the connection, when spoler runs with --auth-file
+    Auth {
+        token: String,
+    },
+    //removes a pending task (and so its future repetitions) from whichever queue holds it
+    Cancel {
+        id: String,
+    },
+    //changes the eta, the repeat interval or the payload of the pending task,
+    //wherever it is in its queue
+    Reschedule {
+        id: String,
+        #[serde(flatten)]
+        changes: TaskChanges,
+    },
+    //dispatches the pending task now, a recurring task keeps its schedule
+    RunNow {
+        id: String,
+    },
+    //returns the lifecycle status of a task
+    Status {
+        id: String,
+    },
+    //returns the length and the counters of every queue
+    Stats,
+    //pages through the pending tasks of a queue, in eta order
+    List {
+        #[serde(deserialize_with = "queue_name")]
+        queue: String,
+        #[serde(default)]
+        offset: usize,
+        #[serde(default = "default_list_limit")]
+        limit: usize,
+    },
+    //returns the next due task of a queue, or of all the queues when no queue is given
+    Peek {
+        #[serde(default, deserialize_with = "optional_queue_name")]
+        queue: Option<String>,
+    },
+    //the connection consumes the due tasks of this queues, they are written to it instead of
+    //being sent to the local worker
+    Subscribe {
+        #[serde(deserialize_with = "queue_names")]
+        queues: Vec<String>,
+    },
+    //takes up to max due tasks from the queues, they go back to their queue if they are
+    //not acked or nacked before the visibility timeout
+    Lease {
+        #[serde(deserialize_with = "queue_names")]
+        queues: Vec<String>,
+        #[serde(default = "default_lease_max")]
+        max: usize,
+        #[serde(default = "default_visibility_timeout")]
+        visibility_timeout: u32,
+    },
+    //the leased task finished successfully
+    Ack {
+        lease_id: String,
+    },
+    //the leased task failed, by default it goes back to its queue after the delay
+    Nack {
+        lease_id: String,
+        error: Option<String>,
+        #[serde(default = "default_requeue")]
+        requeue: bool,
+        #[serde(default)]
+        delay: u32,
+    },
+    //the leased tasks are still running, extends their visibility timeout
+    Heartbeat {
+        lease_ids: Vec<String>,
+        #[serde(default = "default_visibility_timeout")]
+        visibility_timeout: u32,
+    },
+    //inserts all the tasks in their queues, or none of them if any is invalid
+    Batch {
+        tasks: Vec<Value>,
+    },
+    //switches the framing of the connection, after the reply both sides use the new one
+    Framing {
+        framing: Framing,
+    },
+    //stops taking new tasks, waits for the running ones and writes the snapshot,
+    //the timeout (in seconds) defaults to the --shutdown-timeout of the node
+    Drain {
+        timeout: Option<u64>,
+    },
+    //creates an empty queue, the values missing from the config are the defaults of the node
+    CreateQueue {
+        #[serde(deserialize_with = "queue_name")]
+        name: String,
+        #[serde(flatten)]
+        config: QueueConfig,
+    },
+    //deletes a queue, with force its pending tasks are cancelled, otherwise it must be empty
+    DeleteQueue {
+        #[serde(deserialize_with = "queue_name")]
+        name: String,
+        #[serde(default)]
+        force: bool,
+    },
+}
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Auth { .. } => "auth",
+            Command::Cancel { .. } => "cancel",
+            Command::Reschedule { .. } => "reschedule",
+            Command::RunNow { .. } => "run_now",
+            Command::Status { .. } => "status",
+            Command::Stats => "stats",
+            Command::List { .. } => "list",
+            Command::Peek { .. } => "peek",
+            Command::Subscribe { .. } => "subscribe",
+            Command::Lease { .. } => "lease",
+            Command::Ack { .. } => "ack",
+            Command::Nack { .. } => "nack",
+            Command::Heartbeat { .. } => "heartbeat",
+            Command::Batch { .. } => "batch",
+            Command::Framing { .. } => "framing",
+            Command::Drain { .. } => "drain",
+            Command::CreateQueue { .. } => "create_queue",
+            Command::DeleteQueue { .. } => "delete_queue",
+        }
+    }
+}
+
+fn default_lease_max() -> usize {
+    1
+}
+
+fn default_visibility_timeout() -> u32 {
+    30
+}
+
+fn default_requeue() -> bool {
+    true
+}
+
+fn default_list_limit() -> usize {
+    50
+}
+
+//a line sent by a client
+#[derive(Debug)]
+pub enum Message {
+    Task(Box<Task>),
+    Command(Command),
+}
+
+impl FromStr for Message {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let value: Value = serde_json::from_str(raw).map_err(|e| format!("Invalid json: {}", e))?;
+        Self::from_value(value)
+    }
+}
+
+impl Message {
+    pub fn from_value(value: Value) -> Result<Self, String> {
+        if value.get("command").is_some() {
+            return serde_json::from_value(value)
+                .map(Message::Command)
+                .map_err(|e| format!("Invalid command: {}", e));
+        }
+        serde_json::from_value(value)
+            .map(|task| Message::Task(Box::new(task)))
+            .map_err(|e| format!("Invalid task: {}", e))
+    }
+}
+
+//the replies are read by the clients too, they know which command they sent,
+//so the command name of a reply is only written
+
+//the outcome of a submitted line
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplyStatus {
+    Accepted,
+    Rejected,
+}
+
+//every task that a client sends is answered with one of this
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Reply {
+    //the id of the task, if we were able to read it
+    pub id: Option<String>,
+    //the queue where the task landed (or was meant to land)
+    pub queue: Option<String>,
+    //the eta resolved by the server, tasks without eta are resolved to the moment they were accepted
+    pub eta: Option<String>,
+    pub status: ReplyStatus,
+    //why the task was rejected
+    pub reason: Option<String>,
+    //the pending tasks removed from the full queue to make room for this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dropped: Vec<String>,
+    //the task was accepted before with the same key, it was not inserted again.
+    //the id, queue and eta are the ones of the task accepted first
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub duplicate: bool,
+    //the pending tasks replaced by this one, in upsert mode
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replaced: Vec<String>,
+    //the task was rejected because its queue was full
+    #[serde(skip)]
+    pub full: bool,
+}
+
+impl Reply {
+    pub fn accepted(task: &Task) -> Self {
+        Self {
+            id: Some(task.id.clone()),
+            queue: Some(task.queue.clone()),
+            eta: Some(task.resolved_eta()),
+            status: ReplyStatus::Accepted,
+            reason: None,
+            dropped: Vec::new(),
+            duplicate: false,
+            replaced: Vec::new(),
+            full: false,
+        }
+    }
+
+    pub fn rejected(id: Option<String>, queue: Option<String>, reason: String) -> Self {
+        Self {
+            id,
+            queue,
+            eta: None,
+            status: ReplyStatus::Rejected,
+            reason: Some(reason),
+            dropped: Vec::new(),
+            duplicate: false,
+            replaced: Vec::new(),
+            full: false,
+        }
+    }
+
+    pub fn full(task: &Task) -> Self {
+        Self {
+            full: true,
+            ..Self::rejected(
+                Some(task.id.clone()),
+                Some(task.queue.clone()),
+                format!("Queue {} is full", task.queue),
+            )
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CancelReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub id: String,
+    //how many pending tasks were removed, 0 means that nothing was scheduled with that id
+    pub removed: usize,
+}
+
+//the reply of reschedule and run_now
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RescheduleReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub id: String,
+    //how many pending tasks were changed or triggered, 0 means that nothing was scheduled with that id
+    pub updated: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub id: String,
+    //null when spoler never saw a task with that id
+    pub task: Option<TaskStatus>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueueStats {
+    pub queue: String,
+    pub len: usize,
+    //null when the queue is unbounded
+    pub capacity: Option<usize>,
+    pub overflow: Overflow,
+    pub remote: bool,
+    #[serde(flatten)]
+    pub counters: QueueCounters,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatsReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub queues: Vec<QueueStats>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub queue: String,
+    //how many tasks the queue holds, for paging
+    pub total: usize,
+    pub offset: usize,
+    pub tasks: Vec<Task>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PeekReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub queue: Option<String>,
+    //null when the queues are empty
+    pub task: Option<Task>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscribeReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub queues: Vec<String>,
+}
+
+//a due task written to a subscribed connection
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DispatchEvent {
+    #[serde(skip_deserializing)]
+    pub event: &'static str,
+    pub task: Task,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LeasedTask {
+    pub lease_id: String,
+    pub expires_at: String,
+    pub task: Task,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LeaseReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    //empty when no task is due
+    pub leases: Vec<LeasedTask>,
+}
+
+//the reply of ack and nack
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AckReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub lease_id: String,
+    //false when the lease already expired, its task is back in the queue
+    pub found: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeartbeatReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub extended: Vec<String>,
+    //the leases that already expired
+    pub expired: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    //accepted when all the tasks were inserted
+    pub status: ReplyStatus,
+    //the outcome of each task, in the same order as they were sent
+    pub tasks: Vec<Reply>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FramingReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub framing: Framing,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DrainReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    //the tasks still running when the timeout passed
+    pub in_flight: usize,
+    //how many pending tasks were written to the snapshot, null without persistence
+    pub snapshot: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateQueueReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub queue: String,
+    pub config: QueueConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteQueueReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub queue: String,
+    //the pending tasks cancelled with the queue
+    pub removed: usize,
+}
+
+//a command that could not be executed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub status: ReplyStatus,
+    pub reason: String,
+    //the command was rejected because of the permissions of the connection
+    #[serde(skip)]
+    pub forbidden: bool,
+}
+
+impl ErrorReply {
+    pub fn new(command: &'static str, reason: String) -> Self {
+        Self {
+            command,
+            status: ReplyStatus::Rejected,
+            reason,
+            forbidden: false,
+        }
+    }
+
+    pub fn forbidden(command: &'static str, reason: String) -> Self {
+        Self {
+            forbidden: true,
+            ..Self::new(command, reason)
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthReply {
+    #[serde(skip_deserializing)]
+    pub command: &'static str,
+    pub status: ReplyStatus,
+}
+
+//everything we can answer to a client, serialized as a json line
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Response {
+    Task(Reply),
+    Cancel(CancelReply),
+    Reschedule(RescheduleReply),
+    Status(StatusReply),
+    Stats(StatsReply),
+    List(ListReply),
+    Peek(PeekReply),
+    Subscribe(SubscribeReply),
+    Dispatch(DispatchEvent),
+    Lease(LeaseReply),
+    Ack(AckReply),
+    Heartbeat(HeartbeatReply),
+    Batch(BatchReply),
+    Framing(FramingReply),
+    Auth(AuthReply),
+    Drain(DrainReply),
+    CreateQueue(CreateQueueReply),
+    DeleteQueue(DeleteQueueReply),
+    Error(ErrorReply),
+}
+
+impl Response {
+    //serialize the response as a json line, ready to be written to the socket
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).expect("Responses are always serializable");
+        line.push('\n');
+        line
+    }
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::str::FromStr;
+
+//what happens with a new task when its queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overflow {
+    //the task is rejected, the client gets the error in the reply
+    #[default]
+    Reject,
+    //the producer waits until the queue has room
+    Block,
+    //the task that was enqueued first is removed to make room
+    DropOldest,
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw {
+            "reject" => Ok(Overflow::Reject),
+            "block" => Ok(Overflow::Block),
+            "drop-oldest" => Ok(Overflow::DropOldest),
+            _ => Err(format!(
+                "Invalid overflow policy {}, use reject, block or drop-oldest",
+                raw
+            )),
+        }
+    }
+}
+
+//the configuration of a queue, the missing values are the defaults of the node
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueConfig {
+    //how many pending tasks the queue holds, 0 is unbounded
+    #[serde(default)]
+    pub capacity: Option<usize>,
+    //what to do with new tasks when the queue is full
+    #[serde(default)]
+    pub overflow: Option<Overflow>,
+    //the queue is consumed only by remote workers, through leases
+    #[serde(default)]
+    pub remote: bool,
+}
+
+//names are used in urls, auth files and logs, so they are kept simple
+pub fn validate_queue_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 64 {
+        return Err(String::from(
+            "Queue names must have between 1 and 64 characters",
+        ));
+    }
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+    if !name.chars().all(valid) {
+        return Err(format!(
+            "Invalid queue name {}, use letters, digits, '-', '_' and '.'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+//queues used to be numbers, the clients that still send them address the queues named after them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawQueueName {
+    Name(String),
+    Index(u64),
+}
+
+impl From<RawQueueName> for String {
+    fn from(raw: RawQueueName) -> Self {
+        match raw {
+            RawQueueName::Name(name) => name,
+            RawQueueName::Index(index) => index.to_string(),
+        }
+    }
+}
+
+pub fn queue_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    RawQueueName::deserialize(deserializer).map(String::from)
+}
+
+pub fn optional_queue_name<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Option::<RawQueueName>::deserialize(deserializer).map(|name| name.map(String::from))
+}
+
+pub fn queue_names<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Vec::<RawQueueName>::deserialize(deserializer)
+        .map(|names| names.into_iter().map(String::from).collect())
+}
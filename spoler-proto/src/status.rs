@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+//the lifecycle of a task inside spoler
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    //waiting in a queue for its eta
+    Pending,
+    //popped from the queue and sent to a worker
+    Dispatched,
+    //a worker is executing it
+    Running,
+    Succeeded,
+    Failed,
+    //removed from its queue before running
+    Cancelled,
+    //removed from its full queue to make room for a newer task
+    Dropped,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaskStatus {
+    pub id: String,
+    pub queue: String,
+    pub state: TaskState,
+    //when the task reached each state for the last time, recurring tasks overwrite them on every run
+    pub pending_at: Option<String>,
+    pub dispatched_at: Option<String>,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub updated_at: String,
+    //the error of the last failed execution, it's kept after later successful runs
+    pub last_error: Option<String>,
+}
+
+//how many tasks went through each queue since the server started
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct QueueCounters {
+    pub enqueued: u64,
+    pub dispatched: u64,
+    pub failed: u64,
+    //new tasks refused because the queue was full
+    pub rejected: u64,
+    //pending tasks removed to make room for new ones
+    pub dropped: u64,
+}
@@ -0,0 +1,543 @@
+use crate::cron::CronSchedule;
+use crate::queue::queue_name;
+use crate::timezone::{from_local, parse_eta, parse_timezone};
+use chrono::prelude::*;
+use chrono::Duration;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashMap;
+use std::ops;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TaskSettings {
+    //represents the seconds of the interval in wich this task should be repeated
+    pub repeat_interval: Option<u32>,
+    //a cron expression of 5 or 6 fields, the task repeats at its times instead of every interval
+    #[serde(default)]
+    pub cron: Option<String>,
+    //represents the times this task should be repeated
+    pub retries: Option<i32>,
+    pub url: Option<String>,
+    pub headers: Option<String>,
+    pub method: Option<String>,
+    //used in python applications
+    pub executor_ref: Option<String>,
+}
+
+impl TaskSettings {
+    //the headers of the request, a json object of names and values
+    pub fn get_headers(&self) -> Result<HashMap<String, String>, String> {
+        let headers: HashMap<String, String> = match &self.headers {
+            Some(headers) => serde_json::from_str(headers)
+                .map_err(|e| format!("The headers must be a json object of strings: {}", e))?,
+            None => HashMap::new(),
+        };
+        for (name, value) in &headers {
+            //the names are http tokens, and the values can't break the line of the header
+            let token = |b: u8| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b);
+            if name.is_empty() || !name.bytes().all(token) {
+                return Err(format!("Invalid header name {}", name));
+            }
+            if value.bytes().any(|b| b != b'\t' && (b < b' ' || b == 0x7f)) {
+                return Err(format!("Invalid value of the header {}", name));
+            }
+        }
+        Ok(headers)
+    }
+}
+
+//this determines how the task is going to be resolved
+#[allow(dead_code)]
+#[derive(Debug, Deserialize, Clone)]
+pub enum TaskType {
+    //the task is resolved via api
+    Api = 1,
+    //the task is resolved sending a tcp message
+    Tcp = 2,
+    //the task is resolved via a python function
+    Python = 3,
+    //we don't know more yet... (WIP)
+    Other = 4,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Task {
+    //the name of the queue this is going to be in
+    #[serde(deserialize_with = "queue_name")]
+    pub queue: String,
+    pub id: String,
+    //example: 2022-07-30T09:44:9.15Z, or a local time of the time zone, 2022-07-30T11:44:09
+    pub eta: Option<String>,
+    pub task_type: i32,
+    //the payload that we are going when processing this task
+    pub payload: Option<String>,
+    //the specific settings is a string in json format,
+    //and need to have one format or other format depending of the type of task
+    pub settings: Option<TaskSettings>,
+    //among the tasks due at the same time, the ones with the highest priority are dispatched first
+    #[serde(default)]
+    pub priority: i32,
+    //the iana time zone of the task, like Europe/Madrid, utc when it's missing.
+    //the local etas, the repetitions and the cron times are in this zone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    //the retries of a producer send the same key, so the task is inserted once.
+    //the id of the task is its key when it's missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    //a task with a key that was already accepted replaces it, instead of being acknowledged as a duplicate
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub upsert: bool,
+    //the order in which spoler accepted the task, for finding the oldest one
+    #[serde(skip)]
+    pub seq: u64,
+    //the instant of the eta, kept by set_eta so the queues don't parse the eta on every comparison.
+    //changing the eta without set_eta leaves it behind
+    #[serde(skip)]
+    pub eta_at: Option<DateTime<Utc>>,
+}
+
+impl Task {
+    pub fn get_queue(&self) -> &str {
+        &self.queue
+    }
+
+    //the key that identifies the retries of the task
+    pub fn dedup_key(&self) -> &str {
+        self.idempotency_key.as_deref().unwrap_or(&self.id)
+    }
+
+    //checks the fields that would make the task fail once it is inside a queue
+    pub fn validate(&self) -> Result<(), String> {
+        let zone = self.zone()?;
+        if let Some(settings) = &self.settings {
+            settings.get_headers()?;
+        }
+        if let Some(eta) = &self.eta {
+            parse_eta(eta, &zone)?;
+        }
+        if let Some(schedule) = self.cron_schedule()? {
+            if self.repeat_interval() > 0 {
+                return Err(String::from(
+                    "A task repeats every repeat_interval or at its cron times, not both",
+                ));
+            }
+            if schedule.next_in(Utc::now(), &zone).is_none() {
+                return Err(String::from("The cron expression never matches"));
+            }
+        }
+        Ok(())
+    }
+
+    //the eta of the task as rfc3339 with the offset of its time zone, tasks without eta are
+    //due right now, or at the next time of their cron expression
+    pub fn resolved_eta(&self) -> String {
+        let zone = self.zone().unwrap_or(chrono_tz::UTC);
+        if let Some(eta) = &self.eta {
+            if let Ok(eta) = parse_eta(eta, &zone) {
+                return eta.to_rfc3339();
+            }
+        }
+        let mut eta = Utc::now();
+        if let Ok(Some(schedule)) = self.cron_schedule() {
+            eta = schedule.next_in(eta, &zone).unwrap_or(eta);
+        }
+        eta.with_timezone(&zone).to_rfc3339()
+    }
+
+    //the time zone of the task, utc when it has none
+    pub fn zone(&self) -> Result<Tz, String> {
+        match &self.timezone {
+            Some(timezone) => parse_timezone(timezone),
+            None => Ok(chrono_tz::UTC),
+        }
+    }
+
+    //the instant of the eta, now when it has none
+    pub fn get_eta(&self) -> DateTime<Utc> {
+        self.eta_instant().unwrap_or_else(Utc::now)
+    }
+
+    //sets the eta, with its offset or in the time zone of the task, and keeps its instant
+    pub fn set_eta(&mut self, eta: String) {
+        self.eta = Some(eta);
+        self.eta_at = None;
+        self.eta_at = self.eta_instant();
+    }
+
+    //fixes the eta to its instant with the offset of the time zone, as resolved_eta gives it
+    pub fn resolve_eta(&mut self) {
+        let eta = self.resolved_eta();
+        self.set_eta(eta);
+    }
+
+    fn eta_instant(&self) -> Option<DateTime<Utc>> {
+        if self.eta_at.is_some() {
+            return self.eta_at;
+        }
+        let zone = self.zone().unwrap_or(chrono_tz::UTC);
+        let eta = parse_eta(self.eta.as_ref()?, &zone).ok()?;
+        Some(eta.with_timezone(&Utc))
+    }
+
+    //the parsed cron expression of the settings, if there is one
+    pub fn cron_schedule(&self) -> Result<Option<CronSchedule>, String> {
+        match self
+            .settings
+            .as_ref()
+            .and_then(|settings| settings.cron.as_ref())
+        {
+            Some(cron) => cron.parse().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn repeat_interval(&self) -> u32 {
+        self.settings
+            .as_ref()
+            .and_then(|settings| settings.repeat_interval)
+            .unwrap_or(0)
+    }
+
+    pub fn should_run_now(&self) -> bool {
+        self.get_eta() <= Utc::now()
+    }
+
+    pub fn should_reschedule(&self) -> bool {
+        //no settings we do nothing
+        if self.settings.is_none() {
+            return false;
+        }
+
+        let settings = self.settings.as_ref().unwrap();
+        //no repeat interval nor cron, we do nothing
+        if self.repeat_interval() == 0 && settings.cron.is_none() {
+            return false;
+        }
+
+        //if retries is 0 we finished our work
+        let retries = settings.retries.unwrap_or(-1);
+        if retries != -1 && retries <= 0 {
+            return false;
+        }
+        //a schedule without more times is finished too, a repetition without eta would be due forever
+        self.get_next_eta().is_some()
+    }
+
+    pub fn get_next(&self) -> Task {
+        let mut next = Task {
+            eta: None,
+            queue: self.queue.clone(),
+            id: self.id.clone(),
+            payload: self.payload.clone(),
+            task_type: self.task_type,
+            settings: Some(self.settings.clone().unwrap() - 1),
+            priority: self.priority,
+            timezone: self.timezone.clone(),
+            idempotency_key: self.idempotency_key.clone(),
+            upsert: self.upsert,
+            seq: self.seq,
+            eta_at: None,
+        };
+        if let Some(eta) = self.get_next_eta() {
+            next.set_eta(eta);
+        }
+        next
+    }
+
+    //a one-shot copy of the task, due at the eta, for running it again after a failure or out of
+    //its schedule. it does not repeat, the next repetition of recurring tasks is already in the queue
+    pub fn get_retry(&self, eta: DateTime<Utc>) -> Task {
+        let mut retry = self.clone();
+        retry.set_eta(eta.to_rfc3339());
+        if let Some(settings) = retry.settings.as_mut() {
+            settings.repeat_interval = None;
+            settings.cron = None;
+        }
+        retry
+    }
+
+    //the position of the task in its queue: the earliest eta first, then the highest priority,
+    //then the one accepted first. spoler resolves the missing etas when it accepts the tasks
+    fn order_key(&self) -> (Option<DateTime<Utc>>, Reverse<i32>, u64) {
+        (self.eta_instant(), Reverse(self.priority), self.seq)
+    }
+
+    fn get_next_eta(&self) -> Option<String> {
+        let eta = self.eta_instant()?;
+        let zone = self.zone().unwrap_or(chrono_tz::UTC);
+        //the times of the cron that were missed while the task waited are skipped, as cron does
+        if let Ok(Some(schedule)) = self.cron_schedule() {
+            let next = schedule.next_in(eta.max(Utc::now()), &zone)?;
+            return Some(next.with_timezone(&zone).to_rfc3339());
+        }
+        let interval = Duration::seconds(self.repeat_interval().into());
+        //the intervals of whole days are added to the local time, so a daily task keeps its hour
+        //across the dst changes, and from_local decides the days that hour is repeated or skipped.
+        //the local time is the one of the eta as written, which keeps it for the skipped hours
+        if interval.num_seconds() % Duration::days(1).num_seconds() == 0 {
+            let local = match DateTime::parse_from_rfc3339(self.eta.as_ref()?) {
+                Ok(eta) => eta.naive_local(),
+                Err(_) => eta.with_timezone(&zone).naive_local(),
+            };
+            return Some(from_local(&zone, local + interval).to_rfc3339());
+        }
+        Some((eta + interval).with_timezone(&zone).to_rfc3339())
+    }
+}
+
+//the changes to a pending task, the fields that are missing keep their value
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TaskChanges {
+    //the new eta, with its offset or in the time zone of the task.
+    //the next repetitions of recurring tasks count from it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eta: Option<String>,
+    //the new interval of a recurring task, it replaces its cron. 0 stops the repetitions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_interval: Option<u32>,
+    //the new cron expression of a recurring task, it replaces its interval.
+    //without a new eta the task is due at the next time of the expression
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+}
+
+impl TaskChanges {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.eta.is_none()
+            && self.repeat_interval.is_none()
+            && self.cron.is_none()
+            && self.payload.is_none()
+        {
+            return Err(String::from(
+                "Nothing to change, send an eta, a repeat_interval, a cron or a payload",
+            ));
+        }
+        if self.repeat_interval.is_some() && self.cron.is_some() {
+            return Err(String::from(
+                "A task repeats every repeat_interval or at its cron times, not both",
+            ));
+        }
+        if let Some(cron) = &self.cron {
+            let schedule = cron.parse::<CronSchedule>()?;
+            if schedule.next_in(Utc::now(), &chrono_tz::UTC).is_none() {
+                return Err(String::from("The cron expression never matches"));
+            }
+        }
+        //the local etas are read in the time zone of the task when they are applied
+        if let Some(eta) = &self.eta {
+            parse_eta(eta, &chrono_tz::UTC)?;
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, task: &mut Task) {
+        if let Some(eta) = &self.eta {
+            task.eta = Some(eta.clone());
+            task.resolve_eta();
+        }
+        if let Some(repeat_interval) = self.repeat_interval {
+            let settings = task.settings.get_or_insert_with(TaskSettings::default);
+            settings.repeat_interval = Some(repeat_interval);
+            settings.cron = None;
+        }
+        if let Some(cron) = &self.cron {
+            let settings = task.settings.get_or_insert_with(TaskSettings::default);
+            settings.cron = Some(cron.clone());
+            settings.repeat_interval = None;
+            //without a new eta, the task is due at the next time of its new cron
+            if self.eta.is_none() {
+                task.eta = None;
+                task.resolve_eta();
+            }
+        }
+        if let Some(payload) = &self.payload {
+            task.payload = Some(payload.clone());
+        }
+    }
+}
+
+//tasks are equal when they have the same place in the queue, not the same content
+impl std::cmp::PartialEq for Task {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl std::cmp::Eq for Task {}
+
+impl std::cmp::PartialOrd for Task {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl std::cmp::Ord for Task {
+    //compare by eta, priority and sequence
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.order_key().cmp(&other.order_key())
+    }
+}
+
+impl ops::Sub<i32> for TaskSettings {
+    type Output = TaskSettings;
+
+    fn sub(self, _rhs: i32) -> TaskSettings {
+        TaskSettings {
+            repeat_interval: self.repeat_interval,
+            cron: self.cron,
+            retries: self.retries.map(|retries| retries - 1),
+            url: self.url,
+            method: self.method,
+            headers: self.headers,
+            executor_ref: self.executor_ref,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repeating(eta: &str, repeat_interval: u32) -> Task {
+        serde_json::from_value(serde_json::json!({
+            "queue": "0",
+            "id": "a",
+            "eta": eta,
+            "task_type": 4,
+            "payload": null,
+            "settings": { "repeat_interval": repeat_interval, "retries": null },
+            "timezone": "Europe/Madrid",
+        }))
+        .unwrap()
+    }
+
+    fn etas(mut task: Task, count: usize) -> Vec<String> {
+        let mut etas = Vec::new();
+        for _ in 0..count {
+            task = task.get_next();
+            etas.push(task.eta.clone().unwrap());
+        }
+        etas
+    }
+
+    fn due(id: &str, eta: &str, priority: i32, seq: u64) -> Task {
+        let mut task: Task = serde_json::from_value(serde_json::json!({
+            "queue": "0",
+            "id": id,
+            "eta": eta,
+            "task_type": 4,
+            "payload": null,
+            "settings": null,
+            "priority": priority,
+        }))
+        .unwrap();
+        task.seq = seq;
+        task
+    }
+
+    fn ids(mut tasks: Vec<Task>) -> Vec<String> {
+        tasks.sort();
+        tasks.into_iter().map(|task| task.id).collect()
+    }
+
+    #[test]
+    fn orders_by_eta_then_priority_then_seq() {
+        let tasks = vec![
+            due("later", "2030-01-01T10:00:01Z", 10, 0),
+            due("low", "2030-01-01T10:00:00Z", 0, 1),
+            due("second", "2030-01-01T10:00:00Z", 5, 3),
+            due("first", "2030-01-01T10:00:00Z", 5, 2),
+            //the same instant with another offset
+            due("earlier", "2030-01-01T10:59:59+01:00", 0, 4),
+        ];
+        assert_eq!(
+            ids(tasks.clone()),
+            ["earlier", "first", "second", "low", "later"]
+        );
+
+        //the instants kept by set_eta give the same order
+        let resolved = tasks
+            .into_iter()
+            .map(|mut task| {
+                task.resolve_eta();
+                assert!(task.eta_at.is_some());
+                task
+            })
+            .collect();
+        assert_eq!(
+            ids(resolved),
+            ["earlier", "first", "second", "low", "later"]
+        );
+    }
+
+    #[test]
+    fn set_eta_keeps_the_instant_of_the_new_eta() {
+        let mut task = due("a", "2030-01-01T10:00:00", 0, 0);
+        task.timezone = Some(String::from("Europe/Madrid"));
+        task.resolve_eta();
+        assert_eq!(task.eta.as_deref(), Some("2030-01-01T10:00:00+01:00"));
+        assert_eq!(
+            task.get_eta(),
+            "2030-01-01T09:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+
+        let retry = task.get_retry("2030-01-02T00:00:00Z".parse().unwrap());
+        assert_eq!(
+            retry.get_eta(),
+            "2030-01-02T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert!(task < retry);
+    }
+
+    #[test]
+    fn headers_must_be_valid_http_headers() {
+        let settings = |headers: &str| TaskSettings {
+            headers: Some(headers.to_string()),
+            ..Default::default()
+        };
+        let headers = settings(r#"{"content-type":"application/json","x-tab":"a\tb"}"#);
+        assert_eq!(headers.get_headers().unwrap().len(), 2);
+        assert_eq!(TaskSettings::default().get_headers(), Ok(HashMap::new()));
+        for headers in [
+            r#"{"a":"b\nc"}"#,
+            r#"{"a b":"c"}"#,
+            r#"{"":"c"}"#,
+            r#"{"a":1}"#,
+            "not json",
+        ] {
+            assert!(settings(headers).get_headers().is_err(), "{}", headers);
+        }
+    }
+
+    #[test]
+    fn daily_intervals_keep_the_local_time() {
+        assert_eq!(
+            etas(repeating("2026-03-28T02:00:00+01:00", 86400), 2),
+            ["2026-03-29T02:00:00+01:00", "2026-03-30T02:00:00+02:00"]
+        );
+        assert_eq!(
+            etas(repeating("2026-10-24T02:30:00+02:00", 86400), 2),
+            ["2026-10-25T02:30:00+02:00", "2026-10-26T02:30:00+01:00"]
+        );
+        assert_eq!(
+            etas(repeating("2026-03-22T09:00:00+01:00", 7 * 86400), 1),
+            ["2026-03-29T09:00:00+02:00"]
+        );
+    }
+
+    #[test]
+    fn other_intervals_count_the_elapsed_time() {
+        assert_eq!(
+            etas(repeating("2026-03-29T01:30:00+01:00", 3600), 2),
+            ["2026-03-29T03:30:00+02:00", "2026-03-29T04:30:00+02:00"]
+        );
+        assert_eq!(
+            etas(repeating("2026-10-25T02:30:00+02:00", 3600), 1),
+            ["2026-10-25T02:30:00+01:00"]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use chrono::{DateTime, Duration, FixedOffset, LocalResult, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+
+//an iana time zone, like Europe/Madrid
+pub fn parse_timezone(raw: &str) -> Result<Tz, String> {
+    raw.parse().map_err(|_| {
+        format!(
+            "Invalid time zone {}, use an iana name like Europe/Madrid",
+            raw
+        )
+    })
+}
+
+//an eta with its offset, like 2030-01-01T02:00:00+01:00, or a local time of the zone,
+//like 2030-01-01T02:00:00. the eta is given back in the zone
+pub fn parse_eta(raw: &str, zone: &Tz) -> Result<DateTime<FixedOffset>, String> {
+    if let Ok(eta) = DateTime::parse_from_rfc3339(raw) {
+        //the etas already written in the zone stay as they are, the skipped local times too
+        let local = from_local(zone, eta.naive_local());
+        if local == eta && local.offset() == eta.offset() {
+            return Ok(eta);
+        }
+        let eta = eta.with_timezone(zone);
+        return Ok(eta.with_timezone(&eta.offset().fix()));
+    }
+    raw.parse::<NaiveDateTime>()
+        .map(|local| from_local(zone, local))
+        .map_err(|_| format!("Invalid eta: {}", raw))
+}
+
+//the instant of a local time of the zone. the local times that a dst change repeats are
+//their first occurrence, and the ones it skips are moved forward by the length of the gap,
+//so 02:30 of a night that jumps from 02:00 to 03:00 is 03:30. those keep the offset from
+//before the change, 02:30+01:00, so their local time is still the one that was asked for
+pub fn from_local(zone: &Tz, local: NaiveDateTime) -> DateTime<FixedOffset> {
+    let time = match zone.from_local_datetime(&local) {
+        LocalResult::Single(time) => time,
+        LocalResult::Ambiguous(first, second) => first.min(second),
+        LocalResult::None => {
+            //the offset before the change, read a day earlier
+            let offset = zone
+                .offset_from_utc_datetime(&(local - Duration::days(1)))
+                .fix();
+            return offset.from_local_datetime(&local).unwrap();
+        }
+    };
+    time.with_timezone(&time.offset().fix())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Madrid;
+
+    fn local(raw: &str) -> NaiveDateTime {
+        raw.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_iana_names() {
+        assert_eq!(parse_timezone("Europe/Madrid"), Ok(Madrid));
+        assert!(parse_timezone("Europe/Nowhere").is_err());
+        assert!(parse_timezone("+01:00").is_err());
+    }
+
+    #[test]
+    fn local_times_are_read_in_the_zone() {
+        assert_eq!(
+            from_local(&Madrid, local("2026-07-01T10:00:00")).to_rfc3339(),
+            "2026-07-01T10:00:00+02:00"
+        );
+        assert_eq!(
+            from_local(&Madrid, local("2026-12-01T10:00:00")).to_rfc3339(),
+            "2026-12-01T10:00:00+01:00"
+        );
+    }
+
+    #[test]
+    fn skipped_local_times_keep_the_offset_before_the_gap() {
+        let eta = from_local(&Madrid, local("2026-03-29T02:30:00"));
+        assert_eq!(eta.to_rfc3339(), "2026-03-29T02:30:00+01:00");
+        assert_eq!(
+            eta.with_timezone(&Madrid).to_rfc3339(),
+            "2026-03-29T03:30:00+02:00"
+        );
+    }
+
+    #[test]
+    fn repeated_local_times_are_the_first_occurrence() {
+        assert_eq!(
+            from_local(&Madrid, local("2026-10-25T02:30:00")).to_rfc3339(),
+            "2026-10-25T02:30:00+02:00"
+        );
+    }
+
+    #[test]
+    fn etas_are_given_back_in_the_zone() {
+        let eta = |raw| parse_eta(raw, &Madrid).map(|eta| eta.to_rfc3339());
+        assert_eq!(
+            eta("2026-07-01T08:00:00Z"),
+            Ok(String::from("2026-07-01T10:00:00+02:00"))
+        );
+        assert_eq!(
+            eta("2026-07-01T10:00:00"),
+            Ok(String::from("2026-07-01T10:00:00+02:00"))
+        );
+        //the skipped times written as from_local gives them back stay as they are
+        assert_eq!(
+            eta("2026-03-29T02:30:00+01:00"),
+            Ok(String::from("2026-03-29T02:30:00+01:00"))
+        );
+        assert_eq!(
+            eta("2026-10-25T02:30:00+01:00"),
+            Ok(String::from("2026-10-25T02:30:00+01:00"))
+        );
+        assert!(eta("tomorrow").is_err());
+    }
+}
@@ -1,138 +1,1382 @@
-use std::clone::Clone;
-use std::future::Future;
-use std::net::SocketAddr;
-use std::pin::Pin;
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::AsyncBufReadExt;
-use tokio::io::BufReader;
-use tokio::net::TcpStream;
-use tokio::sync::mpsc::Sender;
-use tokio::sync::Mutex;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::futures::Notified;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tokio_tungstenite::WebSocketStream;
 
+mod auth;
+mod dedup;
+mod leases;
+mod lifecycle;
+mod limits;
 pub mod queue;
-mod task;
+mod registry;
+mod status;
+mod subscriptions;
 mod worker;
 
+pub use auth::Tokens;
+use auth::{Action, Permissions};
+use dedup::Claim;
+pub use dedup::Deduplication;
+use leases::Leases;
+pub use lifecycle::Lifecycle;
+pub use limits::Limits;
 pub use queue::Heap;
 use queue::Queue;
-pub use task::{Task, TaskSettings, TaskType};
+use registry::AppQueue;
+pub use registry::QueueRegistry;
+pub use spoler_proto::{
+    AckReply, AuthReply, BatchReply, CancelReply, Command, CreateQueueReply, DeleteQueueReply,
+    DispatchEvent, DrainReply, ErrorReply, Framing, FramingReply, HeartbeatReply, LeaseReply,
+    LeasedTask, ListReply, Message, Overflow, PeekReply, QueueConfig, QueueStats, Reply,
+    ReplyStatus, RescheduleReply, Response, StatsReply, StatusReply, SubscribeReply, Task,
+    TaskChanges,
+};
+pub use status::StatusRegistry;
+use subscriptions::Subscriptions;
 
-type AppQueue<T> = Arc<Mutex<T>>;
+//the longest the dispatcher sleeps without reading the clock again
+const MAX_SLEEP: Duration = Duration::from_secs(60);
 
 pub struct App<T> {
-    pub queues: Vec<AppQueue<T>>,
+    //the queues by name, with their configuration
+    pub queues: QueueRegistry<T>,
     pub sender: Sender<Task>,
+    pub statuses: StatusRegistry,
+    //the tokens allowed to connect, when authentication is enabled
+    pub tokens: Option<Arc<Tokens>>,
+    //draining state and in flight tasks, shared with the listeners and the worker
+    pub lifecycle: Lifecycle,
+    //the capacity of the queues, and what to do with new tasks when they are full
+    pub limits: Limits,
+    //the keys of the tasks accepted lately, so the retries of the producers are inserted once
+    pub dedup: Deduplication,
+    //notified every time tasks leave a queue, for the producers waiting for room
+    freed: Arc<Notify>,
+    //notified every time tasks enter a queue or change their eta, for the dispatcher sleeping
+    //until the next eta. it keeps the notification when the dispatcher is busy
+    scheduled: Arc<Notify>,
+    //the next sequence number of an accepted task
+    next_seq: Arc<AtomicU64>,
+    subscriptions: Subscriptions,
+    leases: Leases,
+    //the permissions of the token this connection authenticated with
+    permissions: Option<Permissions>,
+    //the due tasks pushed to this connection, when it's subscribed to some queues
+    subscription: Option<Receiver<Task>>,
 }
 
 impl<T> App<T>
 where
     T: Queue<Task> + std::fmt::Debug,
 {
-    pub fn new(s: Sender<Task>) -> Self {
+    pub fn new(s: Sender<Task>, statuses: StatusRegistry) -> Self {
         Self {
-            queues: Vec::new(),
+            queues: QueueRegistry::new(),
             sender: s,
+            statuses,
+            tokens: None,
+            lifecycle: Lifecycle::default(),
+            limits: Limits::default(),
+            dedup: Deduplication::default(),
+            freed: Arc::new(Notify::new()),
+            scheduled: Arc::new(Notify::new()),
+            next_seq: Arc::new(AtomicU64::new(0)),
+            subscriptions: Subscriptions::new(),
+            leases: Leases::new(),
+            permissions: None,
+            subscription: None,
         }
     }
 
-    //adds a new queue to the set of queues, and returns the position that it ocuppies in the list of queues
-    //this can be used while declaring tasks, etc...
+    //adds a new empty queue, the values missing from its config are the defaults of the node
+    pub fn create_queue(&self, name: &str, config: QueueConfig) -> Result<(), String> {
+        self.queues.create(name, config)
+    }
+
+    //deletes the queue, with force its pending tasks are cancelled, otherwise it must be empty.
+    //returns how many tasks were cancelled
+    pub async fn delete_queue(&mut self, name: &str, force: bool) -> Result<usize, String> {
+        let (queue, _) = self.get_queue(name)?;
+        //the lock is held while the queue leaves the registry,
+        //so nobody inserts in it after the tasks are taken out
+        let mut queue_lock = queue.lock().await;
+        if !force && queue_lock.len() > 0 {
+            return Err(format!(
+                "Queue {} has {} pending tasks, delete it with force",
+                name,
+                queue_lock.len()
+            ));
+        }
+        self.queues.delete(name)?;
+        let ids: Vec<String> = queue_lock
+            .items()
+            .into_iter()
+            .map(|task| {
+                self.dedup.forget(task);
+                task.id.clone()
+            })
+            .collect();
+        let removed = queue_lock.remove(|_: &Task| true);
+        drop(queue_lock);
 
-    pub fn add_new_empty_queue(&mut self) -> () {
-        self.queues.push(Arc::new(Mutex::new(T::new())))
+        for id in ids {
+            self.statuses.cancelled(&id);
+        }
+        self.statuses.remove_queue(name);
+        self.subscriptions.remove_queue(name);
+        //the producers waiting for room in it find out that it's gone
+        self.freed.notify_waiters();
+        Ok(removed)
     }
 
-    pub async fn run(&mut self, connection: (TcpStream, SocketAddr)) {
-        println!("Acepted and running incoming connection: {}", connection.1);
+    //serves a client connection, it can be plain tcp or tls
+    pub async fn run<S>(&mut self, stream: S, peer: String)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        println!("Accepted and running incoming connection: {}", peer);
+        self.run_connection(stream).await;
+        self.unsubscribe().await;
+    }
 
+    async fn run_connection<S>(&mut self, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
         //create the reader that will be reading from the socket
-        let (mut socket, _) = connection;
-        let (read, _) = socket.split();
-        let mut reader = BufReader::new(read);
-        let mut buffer = String::new();
+        let (mut read, mut write) = tokio::io::split(stream);
+        //the bytes read that don't make a complete frame yet
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut framing = Framing::Json;
 
         loop {
+            //handle the tasks or commands that are already complete,
+            //and let the client know what happened with them
+            let frame = match framing.next_frame(&mut buffer) {
+                Ok(frame) => frame,
+                Err(reason) => {
+                    let response = Response::Error(ErrorReply::new("framing", reason));
+                    let _ = write.write_all(&framing.encode(&response)).await;
+                    return;
+                }
+            };
+            if let Some(frame) = frame {
+                let response = self.handle_message(framing.decode(&frame)).await;
+                if write.write_all(&framing.encode(&response)).await.is_err() {
+                    return;
+                }
+                //the reply to the framing command is the last message in the old framing
+                if let Response::Framing(reply) = response {
+                    framing = reply.framing;
+                }
+                continue;
+            }
+
             tokio::select! {
-                tasks = self.poll_queues() => {
-                    //send this tasks to the proper worker,
-                    //each worker has a queue of tasks to execute in that moment
-                    //what we can do now, is sending back the task to execute now via the tcp client,
-                    //so the client knows that that task needs to be executed in that moment
-                    //this is a TODO
-                    for t in tasks {
-                        let _ = self.sender.send(t).await;
+                Some(task) = next_pushed(&mut self.subscription) => {
+                    //a due task of a queue this connection is subscribed to,
+                    //the client is the one executing it
+                    let event = Response::Dispatch(DispatchEvent {
+                        event: "dispatched",
+                        task: task.clone(),
+                    });
+                    if write.write_all(&framing.encode(&event)).await.is_err() {
+                        self.return_pushed(task).await;
+                        return;
                     }
                 }
-                bytes_read = reader.read_line(&mut buffer) => {
-                    let bytes_read = bytes_read.unwrap();
-                    if bytes_read == 0 {
+                bytes_read = read.read_buf(&mut buffer) => {
+                    //the connection was closed or broken
+                    if bytes_read.unwrap_or(0) == 0 {
                         return;
                     }
-                    let raw_task = std::str::from_utf8(&buffer.as_bytes())
-                        .expect("Invalid data type")
-                        .trim();
+                }
+            };
+        }
+    }
 
-                    //create the task from the raw input
-                    //send the task to the appropiate queue
-                    let task: Task = Task::from_str(raw_task);
+    //serves a websocket connection with the same messages as run, one per websocket message.
+    //text messages carry json and binary messages carry messagepack, the replies and the
+    //pushed tasks use the framing of the connection, json unless the framing command changes it
+    pub async fn run_websocket<S>(&mut self, socket: WebSocketStream<S>, peer: String)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        println!(
+            "Accepted and running incoming websocket connection: {}",
+            peer
+        );
+        self.run_websocket_connection(socket).await;
+        self.unsubscribe().await;
+    }
 
-                    //get the lock of the queue, and insert the new task
-                    let queue_idx = task.get_queue();
-                    self.queues[queue_idx].lock().await.insert(task);
+    async fn run_websocket_connection<S>(&mut self, mut socket: WebSocketStream<S>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut framing = Framing::Json;
+        loop {
+            let response = tokio::select! {
+                Some(task) = next_pushed(&mut self.subscription) => {
+                    Response::Dispatch(DispatchEvent {
+                        event: "dispatched",
+                        task,
+                    })
+                }
+                message = socket.next() => match message {
+                    Some(Ok(WsMessage::Text(text))) => {
+                        self.handle_message(Framing::Json.decode(text.as_bytes())).await
+                    }
+                    Some(Ok(WsMessage::Binary(bytes))) => {
+                        self.handle_message(Framing::Msgpack.decode(&bytes)).await
+                    }
+                    //pings are answered by the websocket itself
+                    Some(Ok(WsMessage::Ping(_) | WsMessage::Pong(_) | WsMessage::Frame(_))) => {
+                        continue
+                    }
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => return,
+                },
+            };
 
-                    //debug
-                    //println!("{:?}", self.queues[queue_idx].lock().unwrap());
+            let message = match framing {
+                Framing::Json => {
+                    let json = framing.encode_message(&response);
+                    WsMessage::Text(String::from_utf8(json).expect("Json is always utf8"))
+                }
+                Framing::Msgpack => WsMessage::Binary(framing.encode_message(&response)),
+            };
+            if socket.send(message).await.is_err() {
+                if let Response::Dispatch(event) = response {
+                    self.return_pushed(event.task).await;
+                }
+                return;
+            }
+            if let Response::Framing(reply) = response {
+                framing = reply.framing;
+            }
+        }
+    }
 
-                    //clean the buffer for the next message
-                    buffer.clear();
+    //ends the subscription of the connection, the tasks pushed to it that were not written
+    //go back to their queues
+    async fn unsubscribe(&mut self) {
+        if let Some(mut receiver) = self.subscription.take() {
+            receiver.close();
+            while let Ok(task) = receiver.try_recv() {
+                self.return_pushed(task).await;
+            }
+        }
+    }
+
+    //puts back a task pushed to a subscriber that did not get it, due as it was. the next
+    //repetition of a recurring task is already in its queue, so it goes back as a one-shot copy
+    async fn return_pushed(&mut self, task: Task) {
+        let task = task.get_retry(task.get_eta());
+        let queue = match self.get_queue(&task.queue) {
+            Ok((queue, _)) => queue,
+            Err(reason) => return self.statuses.failed(&task, reason),
+        };
+        self.statuses.pending(&task);
+        queue.lock().await.insert(task);
+        self.scheduled.notify_one();
+    }
+
+    //sends the due tasks to the worker, and sleeps until the next eta when none is due.
+    //only one instance of the app should run this, the connections and the http api only insert
+    pub async fn dispatch(&mut self) {
+        loop {
+            let due = self.poll_queues().await;
+            if due.is_empty() {
+                //the due tasks wait in their queues, they go to the snapshot
+                if self.lifecycle.is_draining() {
+                    return;
+                }
+                self.wait_next_eta().await;
+                continue;
+            }
+            //send this tasks to the proper worker,
+            //each worker has a queue of tasks to execute in that moment
+            for t in due {
+                //the subscribed connections have preference over the local worker,
+                //it gets the tasks they have no room for
+                if let Some(t) = self.subscriptions.push(t) {
+                    //the worker lets the lifecycle know when the task starts and finishes
+                    self.lifecycle.task_sent(&t);
+                    //a full channel makes the dispatcher wait, so the due tasks stay in their queues
+                    if let Err(e) = self.sender.send(t).await {
+                        eprintln!("Failed to send task {} to the worker: {}", e.0.id, e);
+                        self.statuses
+                            .failed(&e.0, String::from("The worker is not running"));
+                        self.lifecycle.task_not_sent(&e.0);
+                    }
                 }
+            }
+        }
+    }
+
+    //handles a parsed message, being a task or a command
+    pub async fn handle_message(&mut self, message: Result<Message, String>) -> Response {
+        match message {
+            Ok(Message::Task(task)) => Response::Task(self.enqueue(*task).await),
+            Ok(Message::Command(command)) => self.handle_command(command).await,
+            Err(reason) => Response::Task(Reply::rejected(None, None, reason)),
+        }
+    }
+
+    pub async fn handle_command(&mut self, command: Command) -> Response {
+        if let Err(reason) = self.authorize(&command) {
+            return Response::Error(ErrorReply::forbidden(command.name(), reason));
+        }
+
+        match command {
+            Command::Auth { token } => {
+                let status = match self.authenticate(&token) {
+                    true => ReplyStatus::Accepted,
+                    false => ReplyStatus::Rejected,
+                };
+                Response::Auth(AuthReply {
+                    command: "auth",
+                    status,
+                })
+            }
+            Command::Cancel { id } => {
+                let removed = self.cancel(&id).await;
+                Response::Cancel(CancelReply {
+                    command: "cancel",
+                    id,
+                    removed,
+                })
+            }
+            Command::Reschedule { id, changes } => match self.reschedule(&id, &changes).await {
+                Ok(updated) => Response::Reschedule(RescheduleReply {
+                    command: "reschedule",
+                    id,
+                    updated,
+                }),
+                Err(reason) => Response::Error(ErrorReply::new("reschedule", reason)),
+            },
+            Command::RunNow { id } => Response::Reschedule(RescheduleReply {
+                command: "run_now",
+                updated: self.run_now(&id).await,
+                id,
+            }),
+            Command::Status { id } => Response::Status(StatusReply {
+                command: "status",
+                task: self.statuses.get(&id),
+                id,
+            }),
+            Command::Stats => Response::Stats(StatsReply {
+                command: "stats",
+                queues: self.stats().await,
+            }),
+            Command::List {
+                queue,
+                offset,
+                limit,
+            } => match self.list(&queue, offset, limit).await {
+                Ok(reply) => Response::List(reply),
+                Err(reason) => Response::Error(ErrorReply::new("list", reason)),
+            },
+            Command::Peek { queue } => match self.peek(queue.as_deref()).await {
+                Ok(task) => Response::Peek(PeekReply {
+                    command: "peek",
+                    queue,
+                    task,
+                }),
+                Err(reason) => Response::Error(ErrorReply::new("peek", reason)),
+            },
+            Command::Subscribe { queues } => {
+                for queue in &queues {
+                    let reason = match self.get_queue(queue) {
+                        Ok((_, config)) if config.remote => {
+                            format!("Queue {} is consumed through leases", queue)
+                        }
+                        Ok(_) => continue,
+                        Err(reason) => reason,
+                    };
+                    return Response::Error(ErrorReply::new("subscribe", reason));
+                }
+                //subscribing again replaces the previous subscription
+                self.unsubscribe().await;
+                self.subscription = Some(self.subscriptions.subscribe(&queues));
+                Response::Subscribe(SubscribeReply {
+                    command: "subscribe",
+                    queues,
+                })
+            }
+            Command::Lease {
+                queues,
+                max,
+                visibility_timeout,
+            } => match self.lease(&queues, max, visibility_timeout).await {
+                Ok(leases) => Response::Lease(LeaseReply {
+                    command: "lease",
+                    leases,
+                }),
+                Err(reason) => Response::Error(ErrorReply::new("lease", reason)),
+            },
+            Command::Ack { lease_id } => {
+                let task = self.leases.release(&lease_id);
+                if let Some(task) = &task {
+                    self.statuses.succeeded(task);
+                }
+                Response::Ack(AckReply {
+                    command: "ack",
+                    lease_id,
+                    found: task.is_some(),
+                })
+            }
+            Command::Nack {
+                lease_id,
+                error,
+                requeue,
+                delay,
+            } => {
+                let task = self.leases.release(&lease_id);
+                let found = task.is_some();
+                if let Some(task) = task {
+                    let error = error.unwrap_or_else(|| String::from("Nacked by the worker"));
+                    if requeue {
+                        self.requeue(task, error, delay).await;
+                    } else {
+                        self.statuses.failed(&task, error);
+                    }
+                }
+                Response::Ack(AckReply {
+                    command: "nack",
+                    lease_id,
+                    found,
+                })
+            }
+            Command::Batch { tasks } => Response::Batch(self.enqueue_batch(tasks).await),
+            Command::Framing { framing } => Response::Framing(FramingReply {
+                command: "framing",
+                framing,
+            }),
+            Command::Heartbeat {
+                lease_ids,
+                visibility_timeout,
+            } => {
+                let (extended, expired) = lease_ids
+                    .into_iter()
+                    .partition(|lease_id| self.leases.extend(lease_id, visibility_timeout));
+                Response::Heartbeat(HeartbeatReply {
+                    command: "heartbeat",
+                    extended,
+                    expired,
+                })
+            }
+            Command::Drain { timeout } => {
+                let timeout = timeout
+                    .map(Duration::from_secs)
+                    .unwrap_or(self.lifecycle.shutdown_timeout);
+                match self.drain(timeout).await {
+                    Ok(reply) => Response::Drain(reply),
+                    Err(reason) => Response::Error(ErrorReply::new("drain", reason)),
+                }
+            }
+            Command::CreateQueue { name, config } => match self.create_queue(&name, config.clone())
+            {
+                Ok(()) => Response::CreateQueue(CreateQueueReply {
+                    command: "create_queue",
+                    queue: name,
+                    config,
+                }),
+                Err(reason) => Response::Error(ErrorReply::new("create_queue", reason)),
+            },
+            Command::DeleteQueue { name, force } => match self.delete_queue(&name, force).await {
+                Ok(removed) => Response::DeleteQueue(DeleteQueueReply {
+                    command: "delete_queue",
+                    queue: name,
+                    removed,
+                }),
+                Err(reason) => Response::Error(ErrorReply::new("delete_queue", reason)),
+            },
+        }
+    }
+
+    //stops taking tasks and connections, waits for the running tasks up to the timeout,
+    //and writes the pending tasks to the snapshot file. the node keeps serving the queries
+    pub async fn drain(&self, timeout: Duration) -> Result<DrainReply, String> {
+        self.lifecycle.start_draining();
+        let in_flight = self.lifecycle.wait_idle(timeout).await;
+        let snapshot = match &self.lifecycle.snapshot_file {
+            Some(path) => Some(self.write_snapshot(path).await?),
+            None => None,
+        };
+        Ok(DrainReply {
+            command: "drain",
+            in_flight,
+            snapshot,
+        })
+    }
+
+    //writes the queues and their pending and leased tasks as json lines, returns how many tasks
+    //were written. the leased tasks are included, their workers may not be able to ack them after a restart
+    pub async fn write_snapshot(&self, path: &str) -> Result<usize, String> {
+        let mut lines = String::new();
+        let mut tasks = Vec::new();
+        //the queues go first as create_queue commands, so the ones created at runtime come back
+        for (name, queue, config) in self.queues.all() {
+            let command = Command::CreateQueue { name, config };
+            lines.push_str(&serde_json::to_string(&command).map_err(|e| e.to_string())?);
+            lines.push('\n');
+            tasks.extend(queue.lock().await.items().into_iter().cloned());
+        }
+        tasks.extend(self.leases.tasks());
+        //the due tasks that the local worker did not start yet, the next repetitions
+        //of the recurring ones are already in their queues
+        tasks.extend(
+            self.lifecycle
+                .undelivered()
+                .into_iter()
+                .map(|task| task.get_retry(task.get_eta())),
+        );
+        //the oldest first, so they keep their order after a restore
+        tasks.sort_by_key(|task| task.seq);
+
+        for task in &tasks {
+            lines.push_str(&serde_json::to_string(task).map_err(|e| e.to_string())?);
+            lines.push('\n');
+        }
+        //write aside and rename, so a crash never leaves half a snapshot
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, lines).map_err(|e| format!("Failed to write {}: {}", tmp_path, e))?;
+        fs::rename(&tmp_path, path).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        Ok(tasks.len())
+    }
+
+    //loads the queues and the tasks of the snapshot written by the last drain, and removes it so
+    //they are not loaded twice. the queues of the command line keep their config.
+    //returns how many tasks were restored
+    pub async fn restore_snapshot(&mut self, path: &str) -> Result<usize, String> {
+        let raw = match fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(format!("Failed to read {}: {}", path, e)),
+        };
+        let mut restored = 0;
+        for line in raw.lines().filter(|line| !line.trim().is_empty()) {
+            let mut task = match line.parse() {
+                Ok(Message::Task(task)) => *task,
+                Ok(Message::Command(Command::CreateQueue { name, config })) => {
+                    if !self.queues.contains(&name) {
+                        self.create_queue(&name, config)?;
+                    }
+                    continue;
+                }
+                Ok(Message::Command(command)) => {
+                    return Err(format!("Invalid snapshot line: {}", command.name()))
+                }
+                Err(reason) => return Err(format!("Invalid snapshot line: {}", reason)),
             };
+            task.seq = self.next_seq();
+            task.resolve_eta();
+            let queue = match self.get_queue(&task.queue) {
+                Ok((queue, _)) => queue,
+                Err(reason) => {
+                    eprintln!("Dropping task {} of the snapshot: {}", task.id, reason);
+                    continue;
+                }
+            };
+            //the retries of the producers after the restart are still duplicates
+            self.dedup.claim(&task, false);
+            self.statuses.pending(&task);
+            queue.lock().await.insert(task);
+            restored += 1;
         }
+        self.scheduled.notify_one();
+        fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path, e))?;
+        Ok(restored)
     }
 
-    pub async fn poll_queues(&mut self) -> Vec<Task> {
-        //sleep for some time, for now burning the thread
-        tokio::time::sleep(Duration::from_nanos(200)).await;
-        let mut result: Vec<Task> = Vec::new();
-        for i in 0..self.queues.len() {
-            let mut should_run = false;
-            let mut should_reschedule = false;
-            //do this inside a block so the lock is released, and other can use it
+    //sets the permissions of the connection, returns false for unknown tokens
+    pub fn authenticate(&mut self, token: &str) -> bool {
+        match &self.tokens {
+            Some(tokens) => {
+                self.permissions = tokens.get(token).cloned();
+                self.permissions.is_some()
+            }
+            //without authentication every token is fine
+            None => true,
+        }
+    }
+
+    //grants everything to this connection, for listeners where the access is controlled
+    //by other means, like the filesystem permissions of the unix socket
+    pub fn trust(&mut self) {
+        self.permissions = Some(Permissions::all());
+    }
+
+    //true when authentication is disabled, or the token of the connection grants the action
+    fn allowed(&self, action: Action, queue: &str) -> bool {
+        match (&self.tokens, &self.permissions) {
+            (None, _) => true,
+            (Some(_), Some(permissions)) => permissions.allows(action, queue),
+            (Some(_), None) => false,
+        }
+    }
+
+    fn require(&self, action: Action, queue: &str) -> Result<(), String> {
+        if self.allowed(action, queue) {
+            return Ok(());
+        }
+        if self.permissions.is_none() {
+            return Err(String::from("Authentication required"));
+        }
+        Err(format!("Not allowed to {} queue {}", action.name(), queue))
+    }
+
+    //checks the permissions the command needs before running it,
+    //commands over many queues (stats, cancel, peek of all the queues) only see the granted ones
+    fn authorize(&self, command: &Command) -> Result<(), String> {
+        if self.tokens.is_some() && self.permissions.is_none() {
+            return match command {
+                Command::Auth { .. } | Command::Framing { .. } => Ok(()),
+                _ => Err(String::from("Authentication required")),
+            };
+        }
+        match command {
+            Command::Status { id } => match self.statuses.get(id) {
+                Some(status) => self.require(Action::Inspect, &status.queue),
+                None => Ok(()),
+            },
+            Command::List { queue, .. } | Command::Peek { queue: Some(queue) } => {
+                self.require(Action::Inspect, queue)
+            }
+            Command::Subscribe { queues } | Command::Lease { queues, .. } => queues
+                .iter()
+                .try_for_each(|queue| self.require(Action::Consume, queue)),
+            Command::Ack { lease_id } | Command::Nack { lease_id, .. } => {
+                match self.leases.queue_of(lease_id) {
+                    Some(queue) => self.require(Action::Consume, &queue),
+                    None => Ok(()),
+                }
+            }
+            Command::Heartbeat { lease_ids, .. } => lease_ids
+                .iter()
+                .filter_map(|lease_id| self.leases.queue_of(lease_id))
+                .try_for_each(|queue| self.require(Action::Consume, &queue)),
+            Command::Drain { .. } => match &self.permissions {
+                Some(permissions) if !permissions.admin => {
+                    Err(String::from("Not allowed to drain the node"))
+                }
+                _ => Ok(()),
+            },
+            Command::CreateQueue { .. } | Command::DeleteQueue { .. } => match &self.permissions {
+                Some(permissions) if !permissions.admin => {
+                    Err(String::from("Not allowed to create or delete queues"))
+                }
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    //pops up to max due tasks from the queues, leasing them to a remote worker
+    pub async fn lease(
+        &mut self,
+        queues: &[String],
+        max: usize,
+        visibility_timeout: u32,
+    ) -> Result<Vec<LeasedTask>, String> {
+        let queues = queues
+            .iter()
+            .map(|queue| self.get_queue(queue).map(|(queue, _)| queue))
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut leased = Vec::new();
+        //a draining node does not start new tasks
+        if self.lifecycle.is_draining() {
+            return Ok(leased);
+        }
+        for queue in &queues {
+            while leased.len() < max {
+                let task = match self.pop_due(queue).await {
+                    Some(task) => task,
+                    None => break,
+                };
+                let (lease_id, expires_at) = self.leases.lease(task.clone(), visibility_timeout);
+                leased.push(LeasedTask {
+                    lease_id,
+                    expires_at: expires_at.to_rfc3339(),
+                    task,
+                });
+            }
+        }
+        Ok(leased)
+    }
+
+    //puts a failed task back in its queue, to be run again after the delay
+    pub async fn requeue(&mut self, task: Task, error: String, delay: u32) {
+        let mut retry = task.get_retry(Utc::now() + chrono::Duration::seconds(delay.into()));
+        retry.seq = self.next_seq();
+        //the queue may have been deleted while the task was running
+        let queue = match self.get_queue(&retry.queue) {
+            Ok((queue, _)) => queue,
+            Err(reason) => return self.statuses.failed(&retry, reason),
+        };
+        self.statuses.retrying(&retry, error);
+        queue.lock().await.insert(retry);
+        self.scheduled.notify_one();
+    }
+
+    //returns the tasks of the expired leases to their queues, forever
+    pub async fn reap_leases(&mut self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            for task in self.leases.take_expired() {
+                self.requeue(task, String::from("Lease expired"), 0).await;
+            }
+        }
+    }
+
+    pub async fn stats(&self) -> Vec<QueueStats> {
+        let mut stats = Vec::new();
+        for (name, queue, config) in self.queues.all() {
+            if !self.allowed(Action::Inspect, &name) {
+                continue;
+            }
+            stats.push(QueueStats {
+                len: queue.lock().await.len(),
+                capacity: self.limits.capacity(&config),
+                overflow: self.limits.policy(&config),
+                remote: config.remote,
+                counters: self.statuses.counters(&name),
+                queue: name,
+            });
+        }
+        stats
+    }
+
+    //a page of the pending tasks of a queue, sorted by eta
+    pub async fn list(
+        &self,
+        queue: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<ListReply, String> {
+        let (queue_arc, _) = self.get_queue(queue)?;
+        let queue_lock = queue_arc.lock().await;
+        let mut tasks = queue_lock.items();
+        tasks.sort();
+        Ok(ListReply {
+            command: "list",
+            queue: queue.to_string(),
+            total: tasks.len(),
+            offset,
+            tasks: tasks
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .cloned()
+                .collect(),
+        })
+    }
+
+    //the next task that is going to be due, in one queue or in all of them
+    pub async fn peek(&self, queue: Option<&str>) -> Result<Option<Task>, String> {
+        let queues = match queue {
+            Some(queue) => vec![self.get_queue(queue)?.0],
+            None => self
+                .queues
+                .all()
+                .into_iter()
+                .filter(|(name, _, _)| self.allowed(Action::Inspect, name))
+                .map(|(_, queue, _)| queue)
+                .collect(),
+        };
+        let now = Utc::now();
+        let due = |task: &Task| task.get_eta() <= now;
+        let mut next: Option<Task> = None;
+        for queue in queues {
+            let queue_lock = queue.lock().await;
+            let first = queue_lock
+                .first_ready(due, runs_before)
+                .or_else(|| queue_lock.peek());
+            if let Some(task) = first {
+                let before = match (due(task), next.as_ref()) {
+                    (_, None) => true,
+                    (true, Some(next)) if due(next) => runs_before(task, next),
+                    (_, Some(next)) => task < next,
+                };
+                if before {
+                    next = Some(task.clone());
+                }
+            }
+        }
+        Ok(next)
+    }
+
+    fn get_queue(&self, queue: &str) -> Result<(AppQueue<T>, QueueConfig), String> {
+        self.queues.get(queue)
+    }
+
+    //validates and inserts a task in its queue, when the queue is full the overflow policy decides.
+    //a task with the key of one accepted in the dedup window is acknowledged without inserting it,
+    //unless it's an upsert, then it replaces the pending one
+    pub async fn enqueue(&mut self, mut task: Task) -> Reply {
+        if let Err(reason) = self.check(&task) {
+            return Reply::rejected(Some(task.id), Some(task.queue), reason);
+        }
+        task.seq = self.next_seq();
+        //the order of the queue needs fixed etas with their offsets, the tasks without one are
+        //due from now on, and the local ones are read in the time zone of the task
+        task.resolve_eta();
+        //upsert is about this request, the task does not keep it
+        let upsert = std::mem::take(&mut task.upsert);
+
+        loop {
+            //listen before looking at the queue, so a pop in between is not missed
+            let freed = self.freed.notified();
+
+            //the queue can be deleted while the producer waits for room in it
+            let (queue, config) = match self.get_queue(&task.queue) {
+                Ok(queue) => queue,
+                Err(reason) => return Reply::rejected(Some(task.id), Some(task.queue), reason),
+            };
+            //get the lock of the queue, and insert the new task if it fits
+            let mut queue_lock = queue.lock().await;
+            if !self.queues.is_current(&task.queue, &queue) {
+                continue;
+            }
+            //claimed with the queue locked, so a retry racing with its task finds the key
+            let claim = self.dedup.claim(&task, upsert);
+            if let Claim::Duplicate(seen) = claim {
+                return duplicate(&task, seen);
+            }
+            let replacing = replaced_ids(&task, upsert, &claim);
+            let n_replaced = count_replaced(&*queue_lock, &task, &replacing);
+            match self
+                .limits
+                .overflow(&config, queue_lock.len() - n_replaced, 1)
+            {
+                Ok(n_dropped) => {
+                    let mut reply = Reply::accepted(&task);
+                    reply.replaced = self.replace(&mut queue_lock, &task, &replacing);
+                    reply.dropped = self.drop_oldest(&mut queue_lock, n_dropped);
+                    self.statuses.pending(&task);
+                    let upserted = upsert.then(|| task.clone());
+                    queue_lock.insert(task);
+                    drop(queue_lock);
+                    self.scheduled.notify_one();
+                    if let Some(upserted) = upserted {
+                        let replaced = self.replace_elsewhere(&upserted, &replacing).await;
+                        reply.replaced.extend(replaced);
+                    }
+                    return reply;
+                }
+                Err(Overflow::Block) => {
+                    self.dedup.release(&task, claim);
+                    drop(queue_lock);
+                    if !self.wait_freed(freed).await {
+                        return Reply::rejected(Some(task.id), Some(task.queue), draining());
+                    }
+                }
+                Err(_) => {
+                    self.dedup.release(&task, claim);
+                    self.statuses.rejected(&task.queue);
+                    return Reply::full(&task);
+                }
+            }
+        }
+    }
+
+    //validates all the tasks, and inserts all of them or none.
+    //the rejections are the replies of the batch, they are not worth boxing
+    #[allow(clippy::result_large_err)]
+    pub async fn enqueue_batch(&mut self, raw_tasks: Vec<Value>) -> BatchReply {
+        let parsed: Vec<Result<Task, Reply>> = raw_tasks
+            .into_iter()
+            .map(|raw_task| {
+                let task: Task = serde_json::from_value(raw_task)
+                    .map_err(|e| Reply::rejected(None, None, format!("Invalid task: {}", e)))?;
+                match self.check(&task) {
+                    Ok(()) => Ok(task),
+                    Err(reason) => Err(Reply::rejected(Some(task.id), Some(task.queue), reason)),
+                }
+            })
+            .collect();
+
+        //one invalid task rejects the whole batch
+        if parsed.iter().any(|task| task.is_err()) {
+            let replies = parsed
+                .into_iter()
+                .map(|task| match task {
+                    Ok(task) => Reply::rejected(
+                        Some(task.id),
+                        Some(task.queue),
+                        String::from("Another task of the batch is invalid"),
+                    ),
+                    Err(reply) => reply,
+                })
+                .collect();
+            return rejected_batch(replies);
+        }
+        let mut tasks: Vec<Task> = parsed.into_iter().flatten().collect();
+        let mut upserts = Vec::new();
+        for task in tasks.iter_mut() {
+            task.seq = self.next_seq();
+            task.resolve_eta();
+            upserts.push(std::mem::take(&mut task.upsert));
+        }
+
+        let mut names: Vec<String> = tasks.iter().map(|task| task.queue.clone()).collect();
+        names.sort_unstable();
+        names.dedup();
+
+        let (mut locks, mut n_dropped, claims) = loop {
+            let freed = self.freed.notified();
+
+            //the queues can be deleted while the producer waits for room in them
+            let queues = match names
+                .iter()
+                .map(|name| self.get_queue(name))
+                .collect::<Result<Vec<_>, String>>()
+            {
+                Ok(queues) => queues,
+                Err(reason) => {
+                    let replies = tasks
+                        .into_iter()
+                        .map(|task| {
+                            Reply::rejected(Some(task.id), Some(task.queue), reason.clone())
+                        })
+                        .collect();
+                    return rejected_batch(replies);
+                }
+            };
+
+            //take the locks of all the queues involved, always in the same order,
+            //so nobody sees the batch half inserted
+            let mut locks = Vec::new();
+            for (queue, _) in &queues {
+                locks.push(Arc::clone(queue).lock_owned().await);
+            }
+            let deleted = names
+                .iter()
+                .zip(queues.iter())
+                .any(|(name, (queue, _))| !self.queues.is_current(name, queue));
+            if deleted {
+                continue;
+            }
+
+            //the duplicates take no room, and the upserts take the room of the tasks they replace
+            let claims: Vec<Claim> = tasks
+                .iter()
+                .zip(upserts.iter())
+                .map(|(task, upsert)| self.dedup.claim(task, *upsert))
+                .collect();
+            let mut needed = vec![0; names.len()];
+            for ((task, upsert), claim) in tasks.iter().zip(upserts.iter()).zip(claims.iter()) {
+                if let Claim::Duplicate(_) = claim {
+                    continue;
+                }
+                let lock_idx = names.binary_search(&task.queue).unwrap();
+                let replacing = replaced_ids(task, *upsert, claim);
+                needed[lock_idx] += 1;
+                needed[lock_idx] -= count_replaced(&*locks[lock_idx], task, &replacing).min(1);
+            }
+
+            //every queue needs room for its part of the batch
+            let full = match names
+                .iter()
+                .zip(queues.iter())
+                .zip(locks.iter())
+                .zip(needed)
+                .map(|(((name, (_, config)), queue_lock), n)| {
+                    self.limits
+                        .overflow(config, queue_lock.len(), n)
+                        .map_err(|policy| (name, policy))
+                })
+                .collect::<Result<Vec<usize>, (&String, Overflow)>>()
             {
-                let queue_lock = &self.queues[i].lock().await;
-                let task = queue_lock.peek();
-                if task.is_some() && task.unwrap().should_run_now() {
-                    should_run = true;
-                    should_reschedule = task.unwrap().should_reschedule();
+                Ok(n_dropped) => break (locks, n_dropped, claims),
+                Err(full) => full,
+            };
+            //the batch is not inserted, the keys are given back in the reverse order they were taken
+            for (task, claim) in tasks.iter().zip(claims).rev() {
+                self.dedup.release(task, claim);
+            }
+            match full {
+                (_, Overflow::Block) => {
+                    drop(locks);
+                    if !self.wait_freed(freed).await {
+                        let replies = tasks
+                            .into_iter()
+                            .map(|task| {
+                                Reply::rejected(Some(task.id), Some(task.queue), draining())
+                            })
+                            .collect();
+                        return rejected_batch(replies);
+                    }
                 }
+                (full_queue, _) => {
+                    let replies = tasks
+                        .into_iter()
+                        .map(|task| {
+                            if task.queue != *full_queue {
+                                return Reply::rejected(
+                                    Some(task.id),
+                                    Some(task.queue),
+                                    String::from("Another queue of the batch is full"),
+                                );
+                            }
+                            self.statuses.rejected(full_queue);
+                            Reply::full(&task)
+                        })
+                        .collect();
+                    return rejected_batch(replies);
+                }
+            }
+        };
+
+        let mut replies = Vec::new();
+        let mut upserted = Vec::new();
+        for ((task, upsert), claim) in tasks.into_iter().zip(upserts).zip(claims) {
+            if let Claim::Duplicate(seen) = claim {
+                replies.push(duplicate(&task, seen));
+                continue;
+            }
+            let lock_idx = names.binary_search(&task.queue).unwrap();
+            let replacing = replaced_ids(&task, upsert, &claim);
+            let mut reply = Reply::accepted(&task);
+            reply.replaced = self.replace(&mut locks[lock_idx], &task, &replacing);
+            //each task of the batch makes room for itself
+            if n_dropped[lock_idx] > 0 && reply.replaced.is_empty() {
+                n_dropped[lock_idx] -= 1;
+                reply.dropped = self.drop_oldest(&mut locks[lock_idx], 1);
+            }
+            self.statuses.pending(&task);
+            if upsert {
+                upserted.push((replies.len(), task.clone(), replacing));
+            }
+            replies.push(reply);
+            locks[lock_idx].insert(task);
+        }
+        drop(locks);
+        self.scheduled.notify_one();
+        for (reply_idx, task, replacing) in upserted {
+            let replaced = self.replace_elsewhere(&task, &replacing).await;
+            replies[reply_idx].replaced.extend(replaced);
+        }
+        BatchReply {
+            command: "batch",
+            status: ReplyStatus::Accepted,
+            tasks: replies,
+        }
+    }
+
+    fn next_seq(&self) -> u64 {
+        self.next_seq.fetch_add(1, AtomicOrdering::Relaxed)
+    }
+
+    //removes the n tasks of the queue that were enqueued first, returns their ids
+    fn drop_oldest(&self, queue: &mut T, n: usize) -> Vec<String> {
+        let mut dropped = Vec::new();
+        for _ in 0..n {
+            let oldest = match queue.items().into_iter().min_by_key(|task| task.seq) {
+                Some(oldest) => oldest.clone(),
+                None => break,
+            };
+            queue.remove(|task: &Task| task.seq == oldest.seq);
+            self.statuses.dropped(&oldest);
+            dropped.push(oldest.id);
+        }
+        dropped
+    }
+
+    //removes from the queue the pending tasks the upsert replaces, returns their ids
+    fn replace(&self, queue: &mut T, task: &Task, replacing: &[String]) -> Vec<String> {
+        if replacing.is_empty() {
+            return Vec::new();
+        }
+        let replaced: Vec<String> = queue
+            .items()
+            .into_iter()
+            .filter(|pending| replaces(task, replacing, pending))
+            .map(|pending| {
+                //the key of the task is the one of the upsert now, the other keys are free
+                if pending.dedup_key() != task.dedup_key() {
+                    self.dedup.forget(pending);
+                }
+                pending.id.clone()
+            })
+            .collect();
+        queue.remove(|pending: &Task| replaces(task, replacing, pending));
+        for id in &replaced {
+            //the status of the id of the task is the one of the new task
+            if *id != task.id {
+                self.statuses.cancelled(id);
+            }
+        }
+        replaced
+    }
+
+    //an upsert can move a task to another queue, the definitions in the other queues are removed too
+    async fn replace_elsewhere(&self, task: &Task, replacing: &[String]) -> Vec<String> {
+        let mut replaced = Vec::new();
+        for (name, queue, _) in self.queues.all() {
+            if name != task.queue {
+                replaced.extend(self.replace(&mut *queue.lock().await, task, replacing));
             }
-            if should_run {
-                //this is ok, because should pop is true
-                let mut queue_lock = self.queues[i].lock().await;
-                let task = queue_lock.pop().unwrap();
+        }
+        if !replaced.is_empty() {
+            self.freed.notify_waiters();
+        }
+        replaced
+    }
+
+    //waits until a task leaves some queue, returns false if the node starts draining first
+    async fn wait_freed(&self, freed: Notified<'_>) -> bool {
+        tokio::select! {
+            _ = freed => true,
+            _ = self.lifecycle.draining() => false,
+        }
+    }
+
+    //checks that the task can be inserted
+    fn check(&self, task: &Task) -> Result<(), String> {
+        if self.lifecycle.is_draining() {
+            return Err(draining());
+        }
+        task.validate()?;
+        self.get_queue(&task.queue)?;
+        self.require(Action::Enqueue, &task.queue)?;
+        Ok(())
+    }
 
-                if should_reschedule {
-                    queue_lock.insert(task.get_next());
+    //removes every pending task with this id from all the queues,
+    //future repetitions are created from the pending task, so they are gone too
+    pub async fn cancel(&mut self, id: &str) -> usize {
+        let mut removed = 0;
+        for (name, queue, _) in self.queues.all() {
+            //without permission the task is left alone, as if it was not there
+            if !self.allowed(Action::Cancel, &name) {
+                continue;
+            }
+            let mut queue_lock = queue.lock().await;
+            for task in queue_lock.items() {
+                if task.id == id {
+                    self.dedup.forget(task);
                 }
+            }
+            removed += queue_lock.remove(|task: &Task| task.id == id);
+        }
+        if removed > 0 {
+            self.statuses.cancelled(id);
+            self.freed.notify_waiters();
+        }
+        removed
+    }
 
+    //changes the pending tasks with this id, they move to their new place in the queue.
+    //returns how many were changed
+    pub async fn reschedule(&mut self, id: &str, changes: &TaskChanges) -> Result<usize, String> {
+        changes.validate()?;
+        let mut updated = 0;
+        for (name, queue, _) in self.queues.all() {
+            //changing a task is as enqueuing it again
+            if !self.allowed(Action::Enqueue, &name) {
+                continue;
+            }
+            updated += queue
+                .lock()
+                .await
+                .update(|task: &Task| task.id == id, |task| changes.apply(task));
+        }
+        if updated > 0 {
+            self.scheduled.notify_one();
+        }
+        Ok(updated)
+    }
+
+    //makes the pending tasks with this id due now. a recurring task stays where it is,
+    //a one-shot copy of it is due now instead. returns how many tasks were triggered
+    pub async fn run_now(&mut self, id: &str) -> usize {
+        let now = Utc::now();
+        let mut triggered = 0;
+        for (name, queue, _) in self.queues.all() {
+            if !self.allowed(Action::Enqueue, &name) {
+                continue;
+            }
+            let mut queue_lock = queue.lock().await;
+            let copies: Vec<Task> = queue_lock
+                .items()
+                .into_iter()
+                .filter(|task| task.id == id && task.should_reschedule())
+                .map(|task| task.get_retry(now))
+                .collect();
+            triggered += queue_lock.update(
+                |task: &Task| task.id == id && !task.should_reschedule(),
+                |task| task.set_eta(now.to_rfc3339()),
+            );
+            for mut copy in copies {
+                copy.seq = self.next_seq();
+                queue_lock.insert(copy);
+                triggered += 1;
+            }
+        }
+        if triggered > 0 {
+            self.scheduled.notify_one();
+        }
+        triggered
+    }
+
+    //pops the next task of every local queue that has one due
+    pub async fn poll_queues(&mut self) -> Vec<Task> {
+        let mut result: Vec<Task> = Vec::new();
+        //the due tasks wait in their queues, they go to the snapshot
+        if self.lifecycle.is_draining() {
+            return result;
+        }
+        for (_, queue, config) in self.queues.all() {
+            //remote queues are emptied by the workers leasing from them
+            if config.remote {
+                continue;
+            }
+            if let Some(task) = self.pop_due(&queue).await {
                 //add this task to the result, should be run now
                 result.push(task);
             }
         }
         result
     }
+
+    //sleeps until the earliest eta of the local queues, or until a task is inserted or moved,
+    //it may be due before. the clock is read again every minute, in case it's changed
+    async fn wait_next_eta(&self) {
+        let mut sleep = MAX_SLEEP;
+        for (_, queue, config) in self.queues.all() {
+            if config.remote {
+                continue;
+            }
+            if let Some(task) = queue.lock().await.peek() {
+                let until = (task.get_eta() - Utc::now()).to_std().unwrap_or_default();
+                sleep = sleep.min(until);
+            }
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(sleep) => (),
+            _ = self.scheduled.notified() => (),
+            _ = self.lifecycle.draining() => (),
+        }
+    }
+
+    //pops the next task of the queue if it's due, inserting its next repetition
+    async fn pop_due(&mut self, queue: &AppQueue<T>) -> Option<Task> {
+        let now = Utc::now();
+        let mut queue_lock = queue.lock().await;
+        let task = queue_lock.pop_ready(|task| task.get_eta() <= now, runs_before)?;
+        if task.should_reschedule() {
+            queue_lock.insert(task.get_next());
+        } else {
+            self.freed.notify_waiters();
+        }
+        self.statuses.dispatched(&task);
+        Some(task)
+    }
 }
 
 impl<T> Clone for App<T> {
     fn clone(&self) -> Self {
-        let mut queues: Vec<AppQueue<T>> = Vec::new();
-        for q in &self.queues {
-            queues.push(Arc::clone(q));
-        }
         Self {
-            queues: queues,
+            queues: self.queues.clone(),
             sender: self.sender.clone(),
+            statuses: self.statuses.clone(),
+            tokens: self.tokens.clone(),
+            lifecycle: self.lifecycle.clone(),
+            limits: self.limits.clone(),
+            dedup: self.dedup.clone(),
+            freed: self.freed.clone(),
+            scheduled: self.scheduled.clone(),
+            next_seq: self.next_seq.clone(),
+            subscriptions: self.subscriptions.clone(),
+            leases: self.leases.clone(),
+            //a new instance has to authenticate again
+            permissions: None,
+            //subscriptions belong to a connection, a new instance starts without them
+            subscription: None,
         }
     }
 }
+
+//among the due tasks, the highest priority goes first however late the others are,
+//then the order of the queue
+fn runs_before(a: &Task, b: &Task) -> bool {
+    (Reverse(a.priority), a) < (Reverse(b.priority), b)
+}
+
+fn draining() -> String {
+    String::from("The node is draining")
+}
+
+//the acknowledgement of a task that was accepted before, it carries the task accepted first
+fn duplicate(task: &Task, seen: dedup::Seen) -> Reply {
+    Reply {
+        id: Some(seen.id),
+        queue: Some(seen.queue),
+        eta: Some(seen.eta),
+        duplicate: true,
+        ..Reply::accepted(task)
+    }
+}
+
+//the ids of the pending tasks an upsert replaces: the task accepted with its key, and its own id
+fn replaced_ids(task: &Task, upsert: bool, claim: &Claim) -> Vec<String> {
+    if !upsert {
+        return Vec::new();
+    }
+    let mut ids = vec![task.id.clone()];
+    if let Claim::Upsert(seen) = claim {
+        if seen.id != task.id {
+            ids.push(seen.id.clone());
+        }
+    }
+    ids
+}
+
+//only the tasks accepted before the upsert are replaced, not the ones of the same batch after it
+fn replaces(task: &Task, replacing: &[String], pending: &Task) -> bool {
+    pending.seq < task.seq && replacing.contains(&pending.id)
+}
+
+fn count_replaced<T: Queue<Task>>(queue: &T, task: &Task, replacing: &[String]) -> usize {
+    if replacing.is_empty() {
+        return 0;
+    }
+    queue
+        .items()
+        .into_iter()
+        .filter(|pending| replaces(task, replacing, pending))
+        .count()
+}
+
+fn rejected_batch(replies: Vec<Reply>) -> BatchReply {
+    BatchReply {
+        command: "batch",
+        status: ReplyStatus::Rejected,
+        tasks: replies,
+    }
+}
+
+//the next task pushed to a subscribed connection, it never resolves for connections without subscription
+async fn next_pushed(subscription: &mut Option<Receiver<Task>>) -> Option<Task> {
+    match subscription {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,128 @@
+use serde::Deserialize;
+use spoler_proto::queue_names;
+use std::collections::HashMap;
+use std::fs;
+
+//what a token can do with a queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Enqueue,
+    Cancel,
+    //status, stats, list and peek
+    Inspect,
+    //subscribe, lease, ack, nack and heartbeat
+    Consume,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Enqueue => "enqueue",
+            Action::Cancel => "cancel",
+            Action::Inspect => "inspect",
+            Action::Consume => "consume",
+        }
+    }
+}
+
+//the queues granted for an action, "*" grants all of them
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawGrant")]
+pub enum Grant {
+    All,
+    Queues(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawGrant {
+    Wildcard(String),
+    Queues(#[serde(deserialize_with = "queue_names")] Vec<String>),
+}
+
+impl TryFrom<RawGrant> for Grant {
+    type Error = String;
+
+    fn try_from(raw: RawGrant) -> Result<Self, Self::Error> {
+        match raw {
+            RawGrant::Wildcard(wildcard) if wildcard == "*" => Ok(Grant::All),
+            RawGrant::Wildcard(wildcard) => Err(format!(
+                "Invalid grant {}, use \"*\" or a list of queues",
+                wildcard
+            )),
+            RawGrant::Queues(queues) => Ok(Grant::Queues(queues)),
+        }
+    }
+}
+
+impl Default for Grant {
+    fn default() -> Self {
+        Grant::Queues(Vec::new())
+    }
+}
+
+impl Grant {
+    fn allows(&self, queue: &str) -> bool {
+        match self {
+            Grant::All => true,
+            Grant::Queues(queues) => queues.iter().any(|granted| granted == queue),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Permissions {
+    #[serde(default)]
+    pub enqueue: Grant,
+    #[serde(default)]
+    pub cancel: Grant,
+    #[serde(default)]
+    pub inspect: Grant,
+    #[serde(default)]
+    pub consume: Grant,
+    //node wide commands, like drain
+    #[serde(default)]
+    pub admin: bool,
+}
+
+impl Permissions {
+    //every action in every queue
+    pub fn all() -> Self {
+        Self {
+            enqueue: Grant::All,
+            cancel: Grant::All,
+            inspect: Grant::All,
+            consume: Grant::All,
+            admin: true,
+        }
+    }
+
+    pub fn allows(&self, action: Action, queue: &str) -> bool {
+        match action {
+            Action::Enqueue => self.enqueue.allows(queue),
+            Action::Cancel => self.cancel.allows(queue),
+            Action::Inspect => self.inspect.allows(queue),
+            Action::Consume => self.consume.allows(queue),
+        }
+    }
+}
+
+//the tokens that can connect to spoler, loaded from the --auth-file, like:
+//{"producer-token": {"enqueue": ["mail", "reports"], "cancel": ["mail"], "inspect": "*"}, "ops-token": {"admin": true}}
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Tokens {
+    tokens: HashMap<String, Permissions>,
+}
+
+impl Tokens {
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let raw =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        serde_json::from_str(&raw).map_err(|e| format!("Invalid auth file {}: {}", path, e))
+    }
+
+    pub fn get(&self, token: &str) -> Option<&Permissions> {
+        self.tokens.get(token)
+    }
+}
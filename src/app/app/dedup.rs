@@ -0,0 +1,273 @@
+use crate::app::Task;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+//the task accepted under a key
+#[derive(Debug, Clone)]
+pub struct Seen {
+    pub id: String,
+    pub queue: String,
+    pub eta: String,
+    pub accepted_at: DateTime<Utc>,
+}
+
+//what an enqueue is, for the tasks accepted before it
+#[derive(Debug)]
+pub enum Claim {
+    //nothing was accepted with its key in the window
+    New,
+    //the key was accepted in the window, the task is acknowledged without inserting it again
+    Duplicate(Seen),
+    //the key was accepted in the window, and the task replaces it
+    Upsert(Seen),
+}
+
+#[derive(Debug, Default)]
+struct Keys {
+    seen: HashMap<String, Seen>,
+    //the keys in the order they were accepted, for forgetting them when the window ends
+    expiring: VecDeque<(DateTime<Utc>, String)>,
+}
+
+//remembers the keys of the accepted tasks for a window, so the retries of the producers are not
+//inserted twice. it's shared by the app instances
+#[derive(Debug, Clone)]
+pub struct Deduplication {
+    //0 disables the deduplication
+    window: Duration,
+    keys: Arc<Mutex<Keys>>,
+}
+
+impl Default for Deduplication {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Deduplication {
+    pub fn new(window_secs: u32) -> Self {
+        Self {
+            window: Duration::seconds(window_secs.into()),
+            keys: Arc::new(Mutex::new(Keys::default())),
+        }
+    }
+
+    //looks for the key of the task in the window, and records the task under it unless it's
+    //a duplicate. the claim is given back with release when the task is not inserted after all
+    pub fn claim(&self, task: &Task, upsert: bool) -> Claim {
+        if self.window.is_zero() {
+            return Claim::New;
+        }
+        let now = Utc::now();
+        let mut keys = self.keys.lock().unwrap();
+        keys.forget_before(now - self.window);
+
+        let key = task.dedup_key();
+        let previous = keys.seen.get(key).cloned();
+        if let (Some(previous), false) = (&previous, upsert) {
+            return Claim::Duplicate(previous.clone());
+        }
+        keys.seen.insert(
+            key.to_string(),
+            Seen {
+                id: task.id.clone(),
+                queue: task.queue.clone(),
+                eta: task.resolved_eta(),
+                accepted_at: now,
+            },
+        );
+        keys.expiring.push_back((now, key.to_string()));
+        match previous {
+            Some(previous) => Claim::Upsert(previous),
+            None => Claim::New,
+        }
+    }
+
+    //forgets the key of a task that was cancelled, so it can be enqueued again.
+    //the key stays when it belongs to another task by now
+    pub fn forget(&self, task: &Task) {
+        let mut keys = self.keys.lock().unwrap();
+        if keys
+            .seen
+            .get(task.dedup_key())
+            .is_some_and(|seen| seen.id == task.id && seen.queue == task.queue)
+        {
+            keys.seen.remove(task.dedup_key());
+        }
+    }
+
+    //undoes the claim of a task that was rejected
+    pub fn release(&self, task: &Task, claim: Claim) {
+        let mut keys = self.keys.lock().unwrap();
+        match claim {
+            Claim::New => {
+                keys.seen.remove(task.dedup_key());
+            }
+            Claim::Upsert(previous) => {
+                keys.seen.insert(task.dedup_key().to_string(), previous);
+            }
+            Claim::Duplicate(_) => (),
+        }
+    }
+}
+
+impl Keys {
+    fn forget_before(&mut self, limit: DateTime<Utc>) {
+        while let Some((accepted_at, _)) = self.expiring.front() {
+            if *accepted_at > limit {
+                return;
+            }
+            let (accepted_at, key) = self.expiring.pop_front().unwrap();
+            //the key may have been accepted again later, then it stays
+            if self
+                .seen
+                .get(&key)
+                .is_some_and(|seen| seen.accepted_at == accepted_at)
+            {
+                self.seen.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, key: Option<&str>) -> Task {
+        serde_json::from_value(serde_json::json!({
+            "queue": "0",
+            "id": id,
+            "eta": "2030-01-01T00:00:00Z",
+            "task_type": 4,
+            "payload": null,
+            "settings": null,
+            "idempotency_key": key,
+        }))
+        .unwrap()
+    }
+
+    //the id of the task accepted under the key, as a new enqueue sees it
+    fn seen_id(dedup: &Deduplication, key: &str) -> Option<String> {
+        let keys = dedup.keys.lock().unwrap();
+        keys.seen.get(key).map(|seen| seen.id.clone())
+    }
+
+    //moves the keys back in time, as if they were accepted secs before
+    fn age(dedup: &Deduplication, secs: i64) {
+        let mut keys = dedup.keys.lock().unwrap();
+        for seen in keys.seen.values_mut() {
+            seen.accepted_at = seen.accepted_at - Duration::seconds(secs);
+        }
+        for (accepted_at, _) in keys.expiring.iter_mut() {
+            *accepted_at = *accepted_at - Duration::seconds(secs);
+        }
+    }
+
+    #[test]
+    fn a_zero_window_accepts_everything() {
+        let dedup = Deduplication::new(0);
+        assert!(matches!(dedup.claim(&task("a", None), false), Claim::New));
+        assert!(matches!(dedup.claim(&task("a", None), false), Claim::New));
+    }
+
+    #[test]
+    fn retries_with_the_same_key_are_duplicates() {
+        let dedup = Deduplication::new(60);
+        assert!(matches!(dedup.claim(&task("a", None), false), Claim::New));
+        match dedup.claim(&task("a", None), false) {
+            Claim::Duplicate(seen) => assert_eq!(seen.id, "a"),
+            claim => panic!("unexpected claim {:?}", claim),
+        }
+        //the key is the idempotency key when there is one
+        assert!(matches!(
+            dedup.claim(&task("b", Some("k")), false),
+            Claim::New
+        ));
+        match dedup.claim(&task("c", Some("k")), false) {
+            Claim::Duplicate(seen) => assert_eq!(seen.id, "b"),
+            claim => panic!("unexpected claim {:?}", claim),
+        }
+    }
+
+    #[test]
+    fn released_claims_free_the_key() {
+        let dedup = Deduplication::new(60);
+        let claim = dedup.claim(&task("a", None), false);
+        dedup.release(&task("a", None), claim);
+        assert_eq!(seen_id(&dedup, "a"), None);
+
+        //a duplicate leaves the task accepted first alone
+        dedup.claim(&task("a", None), false);
+        let claim = dedup.claim(&task("a", None), false);
+        dedup.release(&task("a", None), claim);
+        assert_eq!(seen_id(&dedup, "a").as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn released_upserts_restore_the_replaced_task() {
+        let dedup = Deduplication::new(60);
+        dedup.claim(&task("a", Some("k")), false);
+        let upsert = task("b", Some("k"));
+        let claim = dedup.claim(&upsert, true);
+        match &claim {
+            Claim::Upsert(seen) => assert_eq!(seen.id, "a"),
+            claim => panic!("unexpected claim {:?}", claim),
+        }
+        assert_eq!(seen_id(&dedup, "k").as_deref(), Some("b"));
+        dedup.release(&upsert, claim);
+        assert_eq!(seen_id(&dedup, "k").as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn a_rejected_batch_is_released_in_reverse() {
+        let dedup = Deduplication::new(60);
+        let batch = [(task("a", Some("k")), false), (task("b", Some("k")), true)];
+        let claims: Vec<Claim> = batch
+            .iter()
+            .map(|(task, upsert)| dedup.claim(task, *upsert))
+            .collect();
+        for ((task, _), claim) in batch.iter().zip(claims).rev() {
+            dedup.release(task, claim);
+        }
+        assert_eq!(seen_id(&dedup, "k"), None);
+    }
+
+    #[test]
+    fn forgotten_tasks_free_their_key() {
+        let dedup = Deduplication::new(60);
+        dedup.claim(&task("a", None), false);
+        dedup.forget(&task("a", None));
+        assert!(matches!(dedup.claim(&task("a", None), false), Claim::New));
+
+        //the key of another task by now stays
+        dedup.claim(&task("b", Some("k")), false);
+        dedup.claim(&task("c", Some("k")), true);
+        dedup.forget(&task("b", Some("k")));
+        assert_eq!(seen_id(&dedup, "k").as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn keys_are_forgotten_after_the_window() {
+        let dedup = Deduplication::new(60);
+        dedup.claim(&task("a", None), false);
+        age(&dedup, 59);
+        assert!(matches!(
+            dedup.claim(&task("a", None), false),
+            Claim::Duplicate(_)
+        ));
+        age(&dedup, 2);
+        assert!(matches!(dedup.claim(&task("a", None), false), Claim::New));
+
+        //a key accepted again is kept for its new window
+        dedup.claim(&task("b", None), false);
+        age(&dedup, 30);
+        dedup.claim(&task("b", None), true);
+        age(&dedup, 31);
+        assert!(matches!(
+            dedup.claim(&task("b", None), false),
+            Claim::Duplicate(_)
+        ));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::app::Task;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub task: Task,
+    pub expires_at: DateTime<Utc>,
+}
+
+//the tasks that remote workers leased and did not ack or nack yet
+#[derive(Debug, Clone, Default)]
+pub struct Leases {
+    leases: Arc<Mutex<HashMap<String, Lease>>>,
+    //for building unique lease ids
+    next_id: Arc<AtomicU64>,
+}
+
+impl Leases {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //leases the task for some seconds, returns the id of the lease and when it expires
+    pub fn lease(&self, task: Task, timeout: u32) -> (String, DateTime<Utc>) {
+        let lease_id = format!(
+            "{}-{}",
+            task.id,
+            self.next_id.fetch_add(1, Ordering::Relaxed)
+        );
+        let expires_at = Utc::now() + Duration::seconds(timeout.into());
+        self.leases
+            .lock()
+            .unwrap()
+            .insert(lease_id.clone(), Lease { task, expires_at });
+        (lease_id, expires_at)
+    }
+
+    //ends the lease, giving back its task
+    pub fn release(&self, lease_id: &str) -> Option<Task> {
+        self.leases
+            .lock()
+            .unwrap()
+            .remove(lease_id)
+            .map(|lease| lease.task)
+    }
+
+    //the queue of the leased task
+    pub fn queue_of(&self, lease_id: &str) -> Option<String> {
+        self.leases
+            .lock()
+            .unwrap()
+            .get(lease_id)
+            .map(|lease| lease.task.queue.clone())
+    }
+
+    //moves the expiration of the lease, returns false if the lease is not there anymore
+    pub fn extend(&self, lease_id: &str, timeout: u32) -> bool {
+        match self.leases.lock().unwrap().get_mut(lease_id) {
+            Some(lease) => {
+                lease.expires_at = Utc::now() + Duration::seconds(timeout.into());
+                true
+            }
+            None => false,
+        }
+    }
+
+    //the tasks that are leased right now
+    pub fn tasks(&self) -> Vec<Task> {
+        self.leases
+            .lock()
+            .unwrap()
+            .values()
+            .map(|lease| lease.task.clone())
+            .collect()
+    }
+
+    //removes and returns the tasks of the expired leases
+    pub fn take_expired(&self) -> Vec<Task> {
+        let now = Utc::now();
+        let mut leases = self.leases.lock().unwrap();
+        let expired: Vec<String> = leases
+            .iter()
+            .filter(|(_, lease)| lease.expires_at <= now)
+            .map(|(lease_id, _)| lease_id.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|lease_id| leases.remove(lease_id))
+            .map(|lease| lease.task)
+            .collect()
+    }
+}
@@ -0,0 +1,126 @@
+use crate::app::Task;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+//the running state of the node, shared by the listeners, the dispatcher and the worker.
+//a draining node stops taking connections and tasks, and lets the running ones finish
+#[derive(Debug, Clone)]
+pub struct Lifecycle {
+    draining: Arc<AtomicBool>,
+    draining_changed: Arc<Notify>,
+    //tasks sent to the local worker that did not finish yet
+    in_flight: Arc<AtomicUsize>,
+    //tasks sent to the local worker that it did not start yet, they go to the snapshot
+    //if the node drains before
+    undelivered: Arc<Mutex<Vec<Task>>>,
+    idle: Arc<Notify>,
+    //how long a drain waits for the running tasks
+    pub shutdown_timeout: Duration,
+    //where the pending tasks are written when draining, if persistence is enabled
+    pub snapshot_file: Option<String>,
+}
+
+impl Lifecycle {
+    pub fn new(shutdown_timeout: Duration, snapshot_file: Option<String>) -> Self {
+        Self {
+            draining: Arc::new(AtomicBool::new(false)),
+            draining_changed: Arc::new(Notify::new()),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            undelivered: Arc::new(Mutex::new(Vec::new())),
+            idle: Arc::new(Notify::new()),
+            shutdown_timeout,
+            snapshot_file,
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+        self.draining_changed.notify_waiters();
+    }
+
+    //resolves once the node starts draining, for stopping the listeners
+    pub async fn draining(&self) {
+        loop {
+            let changed = self.draining_changed.notified();
+            if self.is_draining() {
+                return;
+            }
+            changed.await;
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    //the task is on its way to the worker
+    pub fn task_sent(&self, task: &Task) {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        self.undelivered.lock().unwrap().push(task.clone());
+    }
+
+    //the worker got the task, returns whether it has to run it. a draining node does not start
+    //new tasks, they stay undelivered and finished, so the drain does not wait for them
+    pub fn task_received(&self, task: &Task) -> bool {
+        if self.is_draining() {
+            self.task_finished();
+            return false;
+        }
+        self.forget_undelivered(task);
+        true
+    }
+
+    //the task never reached the worker
+    pub fn task_not_sent(&self, task: &Task) {
+        self.forget_undelivered(task);
+        self.task_finished();
+    }
+
+    //the tasks that the worker did not start
+    pub fn undelivered(&self) -> Vec<Task> {
+        self.undelivered.lock().unwrap().clone()
+    }
+
+    fn forget_undelivered(&self, task: &Task) {
+        let mut undelivered = self.undelivered.lock().unwrap();
+        //the repetitions of a task share its seq, the eta tells them apart
+        if let Some(idx) = undelivered
+            .iter()
+            .position(|sent| sent.seq == task.seq && sent.eta == task.eta)
+        {
+            undelivered.remove(idx);
+        }
+    }
+
+    pub fn task_finished(&self) {
+        if self.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
+
+    //waits until no task is running, or the timeout passes. returns the tasks still running
+    pub async fn wait_idle(&self, timeout: Duration) -> usize {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let idle = self.idle.notified();
+            if self.in_flight() == 0 {
+                return 0;
+            }
+            if tokio::time::timeout_at(deadline, idle).await.is_err() {
+                return self.in_flight();
+            }
+        }
+    }
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(30), None)
+    }
+}
@@ -0,0 +1,42 @@
+use spoler_proto::{Overflow, QueueConfig};
+
+//the capacity and the overflow policy of the queues without their own
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    //0 is unbounded
+    pub default_capacity: usize,
+    pub overflow: Overflow,
+}
+
+impl Limits {
+    pub fn capacity(&self, config: &QueueConfig) -> Option<usize> {
+        match config.capacity.unwrap_or(self.default_capacity) {
+            0 => None,
+            capacity => Some(capacity),
+        }
+    }
+
+    pub fn policy(&self, config: &QueueConfig) -> Overflow {
+        config.overflow.unwrap_or(self.overflow)
+    }
+
+    //how many tasks have to be dropped from a queue with len tasks to insert n more,
+    //the error is the policy that stops the insertion
+    pub fn overflow(&self, config: &QueueConfig, len: usize, n: usize) -> Result<usize, Overflow> {
+        let capacity = match self.capacity(config) {
+            Some(capacity) => capacity,
+            None => return Ok(0),
+        };
+        if len + n <= capacity {
+            return Ok(0);
+        }
+        //waiting or dropping can't make room for more tasks than the capacity
+        if n > capacity {
+            return Err(Overflow::Reject);
+        }
+        match self.policy(config) {
+            Overflow::DropOldest => Ok(len + n - capacity),
+            policy => Err(policy),
+        }
+    }
+}
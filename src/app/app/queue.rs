@@ -2,7 +2,7 @@ use std::collections::VecDeque;
 
 //some helper functions
 fn parent_idx(idx: usize) -> usize {
-    idx / 2
+    (idx - 1) / 2
 }
 
 fn left_child(idx: usize) -> usize {
@@ -13,6 +13,7 @@ fn right_child(idx: usize) -> usize {
     left_child(idx) + 1
 }
 
+#[allow(dead_code)]
 pub struct BasicQueue<T> {
     queue: VecDeque<T>,
 }
@@ -30,12 +31,32 @@ pub trait Queue<T> {
     //an overview if the queue is full
     fn len(&self) -> usize;
     //for adding a new task to the queue
-    fn insert(&mut self, task: T) -> ();
+    fn insert(&mut self, task: T);
     //for seing what is the next task
     fn peek(&self) -> Option<&T>;
     //for getting and deleting the task from the queue
+    #[allow(dead_code)]
     fn pop(&mut self) -> Option<T>;
-    fn bubble_down(&mut self, idx: usize) -> ();
+    //for deleting all the tasks that match the predicate, returns how many were deleted
+    fn remove(&mut self, predicate: impl Fn(&T) -> bool) -> usize;
+    //for changing all the tasks that match the predicate, returns how many were changed
+    fn update(&mut self, predicate: impl Fn(&T) -> bool, change: impl FnMut(&mut T)) -> usize;
+    //for inspecting all the tasks, in no particular order
+    fn items(&self) -> Vec<&T>;
+    //for seeing the task that goes first among the ready ones, by the before order.
+    //the ready tasks have to be the first ones of the queue
+    fn first_ready(
+        &self,
+        ready: impl Fn(&T) -> bool,
+        before: impl Fn(&T, &T) -> bool,
+    ) -> Option<&T>;
+    //for getting and deleting the task that first_ready sees
+    fn pop_ready(
+        &mut self,
+        ready: impl Fn(&T) -> bool,
+        before: impl Fn(&T, &T) -> bool,
+    ) -> Option<T>;
+    fn bubble_down(&mut self, idx: usize);
 }
 
 impl<T> Queue<T> for BasicQueue<T> {
@@ -45,7 +66,7 @@ impl<T> Queue<T> for BasicQueue<T> {
         }
     }
 
-    fn insert(&mut self, task: T) -> () {
+    fn insert(&mut self, task: T) {
         self.queue.push_back(task);
     }
 
@@ -61,8 +82,57 @@ impl<T> Queue<T> for BasicQueue<T> {
         self.queue.pop_front()
     }
 
+    fn remove(&mut self, predicate: impl Fn(&T) -> bool) -> usize {
+        let before = self.queue.len();
+        self.queue.retain(|task| !predicate(task));
+        before - self.queue.len()
+    }
+
+    fn update(&mut self, predicate: impl Fn(&T) -> bool, mut change: impl FnMut(&mut T)) -> usize {
+        let mut updated = 0;
+        for task in self.queue.iter_mut().filter(|task| predicate(task)) {
+            change(task);
+            updated += 1;
+        }
+        updated
+    }
+
+    fn items(&self) -> Vec<&T> {
+        self.queue.iter().collect()
+    }
+
+    fn first_ready(
+        &self,
+        ready: impl Fn(&T) -> bool,
+        before: impl Fn(&T, &T) -> bool,
+    ) -> Option<&T> {
+        self.queue
+            .iter()
+            .take_while(|task| ready(task))
+            .reduce(|first, task| if before(task, first) { task } else { first })
+    }
+
+    fn pop_ready(
+        &mut self,
+        ready: impl Fn(&T) -> bool,
+        before: impl Fn(&T, &T) -> bool,
+    ) -> Option<T> {
+        let mut first = None;
+        for (idx, task) in self
+            .queue
+            .iter()
+            .enumerate()
+            .take_while(|(_, task)| ready(task))
+        {
+            if first.is_none_or(|first| before(task, &self.queue[first])) {
+                first = Some(idx);
+            }
+        }
+        self.queue.remove(first?)
+    }
+
     //optional implementation, is used only inner functions
-    fn bubble_down(&mut self, idx: usize) -> () {}
+    fn bubble_down(&mut self, _idx: usize) {}
 }
 
 impl<T> Queue<T> for Heap<T>
@@ -82,21 +152,12 @@ where
     }
 
     //adds a new entry to the heap
-    fn insert(&mut self, new_entry: T) -> () {
+    fn insert(&mut self, new_entry: T) {
         //for inserting, we add a new entry to the end of the queue and then, we find it's position
         self.data.push(new_entry);
-        let mut entry_idx = self.size;
+        let entry_idx = self.size;
         self.size += 1;
-        println!("inserting entry_idx: {}", entry_idx);
-        while entry_idx > 0 {
-            let parent_idx = parent_idx(entry_idx);
-            if (self.comp)(&self.data[entry_idx], &self.data[parent_idx]) {
-                self.data.swap(entry_idx, parent_idx);
-                entry_idx = parent_idx;
-            } else {
-                break;
-            }
-        }
+        self.bubble_up(entry_idx);
     }
 
     fn peek(&self) -> Option<&T> {
@@ -123,20 +184,114 @@ where
         Some(result)
     }
 
-    fn bubble_down(&mut self, idx: usize) -> () {
-        let left_children_idx = left_child(idx);
-        let right_children_idx = right_child(idx);
-        if left_children_idx < self.size
-            && (self.comp)(&self.data[left_children_idx], &self.data[idx])
-        {
-            self.data.swap(idx, left_children_idx);
-            self.bubble_down(left_children_idx);
+    fn remove(&mut self, predicate: impl Fn(&T) -> bool) -> usize {
+        let before = self.size;
+        self.data.retain(|entry| !predicate(entry));
+        self.size = self.data.len();
+
+        //the remaining entries are not a heap anymore, rebuild it from the last parent up
+        for idx in (0..self.size / 2).rev() {
+            self.bubble_down(idx);
         }
-        if right_children_idx < self.size
-            && (self.comp)(&self.data[right_children_idx], &self.data[idx])
-        {
-            self.data.swap(idx, right_children_idx);
-            self.bubble_down(right_children_idx);
+        before - self.size
+    }
+
+    fn update(&mut self, predicate: impl Fn(&T) -> bool, mut change: impl FnMut(&mut T)) -> usize {
+        let mut updated = 0;
+        for entry in self.data.iter_mut().filter(|entry| predicate(entry)) {
+            change(entry);
+            updated += 1;
+        }
+
+        //the changed entries can be anywhere now, rebuild the heap as remove does
+        if updated > 0 {
+            for idx in (0..self.size / 2).rev() {
+                self.bubble_down(idx);
+            }
+        }
+        updated
+    }
+
+    fn items(&self) -> Vec<&T> {
+        self.data.iter().collect()
+    }
+
+    fn first_ready(
+        &self,
+        ready: impl Fn(&T) -> bool,
+        before: impl Fn(&T, &T) -> bool,
+    ) -> Option<&T> {
+        self.first_ready_idx(ready, before)
+            .map(|idx| &self.data[idx])
+    }
+
+    fn pop_ready(
+        &mut self,
+        ready: impl Fn(&T) -> bool,
+        before: impl Fn(&T, &T) -> bool,
+    ) -> Option<T> {
+        let idx = self.first_ready_idx(ready, before)?;
+        //the last entry takes its place, and moves up or down to where it belongs
+        self.data.swap(idx, self.size - 1);
+        let result = self.data.pop().unwrap();
+        self.size -= 1;
+        if idx < self.size {
+            self.bubble_up(idx);
+            self.bubble_down(idx);
+        }
+        Some(result)
+    }
+
+    fn bubble_down(&mut self, idx: usize) {
+        //swap the entry with the smallest of its children, until both children are bigger
+        let mut smallest = idx;
+        for child_idx in [left_child(idx), right_child(idx)] {
+            if child_idx < self.size && (self.comp)(&self.data[child_idx], &self.data[smallest]) {
+                smallest = child_idx;
+            }
+        }
+        if smallest != idx {
+            self.data.swap(idx, smallest);
+            self.bubble_down(smallest);
+        }
+    }
+}
+
+impl<T> Heap<T>
+where
+    T: PartialOrd,
+{
+    //the parents of a ready entry are ready too, so the ready entries are the top of the heap,
+    //and only them are visited
+    fn first_ready_idx(
+        &self,
+        ready: impl Fn(&T) -> bool,
+        before: impl Fn(&T, &T) -> bool,
+    ) -> Option<usize> {
+        let mut first: Option<usize> = None;
+        let mut pending = vec![0];
+        while let Some(idx) = pending.pop() {
+            if idx >= self.size || !ready(&self.data[idx]) {
+                continue;
+            }
+            if first.is_none_or(|first| before(&self.data[idx], &self.data[first])) {
+                first = Some(idx);
+            }
+            pending.push(left_child(idx));
+            pending.push(right_child(idx));
+        }
+        first
+    }
+
+    fn bubble_up(&mut self, mut idx: usize) {
+        while idx > 0 {
+            let parent_idx = parent_idx(idx);
+            if (self.comp)(&self.data[idx], &self.data[parent_idx]) {
+                self.data.swap(idx, parent_idx);
+                idx = parent_idx;
+            } else {
+                break;
+            }
         }
     }
 }
@@ -149,3 +304,100 @@ where
         f.debug_struct("Heap").field("Data", &self.data).finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: [i32; 12] = [7, 3, 9, 1, 8, 3, 12, 0, 5, 11, 2, 6];
+
+    fn heap(values: &[i32]) -> Heap<i32> {
+        let mut heap = Heap::new();
+        for value in values {
+            heap.insert(*value);
+        }
+        heap
+    }
+
+    fn is_heap(heap: &Heap<i32>) -> bool {
+        heap.size == heap.data.len()
+            && (1..heap.size).all(|idx| heap.data[parent_idx(idx)] <= heap.data[idx])
+    }
+
+    fn pop_all(mut heap: Heap<i32>) -> Vec<i32> {
+        let mut popped = Vec::new();
+        while let Some(value) = heap.pop() {
+            assert!(is_heap(&heap));
+            popped.push(value);
+        }
+        popped
+    }
+
+    fn sorted(mut values: Vec<i32>) -> Vec<i32> {
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn pops_in_order() {
+        let heap = heap(&VALUES);
+        assert!(is_heap(&heap));
+        assert_eq!(heap.peek(), Some(&0));
+        assert_eq!(pop_all(heap), sorted(VALUES.to_vec()));
+    }
+
+    #[test]
+    fn remove_keeps_the_heap() {
+        let mut heap = heap(&VALUES);
+        assert_eq!(heap.remove(|value| value % 2 == 0), 5);
+        assert!(is_heap(&heap));
+        assert_eq!(pop_all(heap), [1, 3, 3, 5, 7, 9, 11]);
+    }
+
+    #[test]
+    fn update_keeps_the_heap() {
+        let mut heap = heap(&VALUES);
+        assert_eq!(
+            heap.update(|value| value % 3 == 0, |value| *value = 20 - *value),
+            6
+        );
+        assert!(is_heap(&heap));
+        let updated = VALUES
+            .iter()
+            .map(|value| if value % 3 == 0 { 20 - value } else { *value })
+            .collect();
+        assert_eq!(pop_all(heap), sorted(updated));
+    }
+
+    #[test]
+    fn pop_ready_takes_the_ready_ones_by_their_own_order() {
+        let mut heap = heap(&VALUES);
+        let ready = |value: &i32| *value <= 6;
+        let before = |a: &i32, b: &i32| a > b;
+        let mut popped = Vec::new();
+        while let Some(first) = heap.first_ready(ready, before).copied() {
+            assert_eq!(heap.pop_ready(ready, before), Some(first));
+            assert!(is_heap(&heap));
+            popped.push(first);
+        }
+        assert_eq!(popped, [6, 5, 3, 3, 2, 1, 0]);
+        assert_eq!(heap.pop_ready(ready, before), None);
+        assert_eq!(pop_all(heap), [7, 8, 9, 11, 12]);
+    }
+
+    #[test]
+    fn basic_queue_pops_ready_ones_from_its_front() {
+        let mut queue = BasicQueue::new();
+        for value in [3, 1, 9, 2] {
+            queue.insert(value);
+        }
+        let ready = |value: &i32| *value < 5;
+        let before = |a: &i32, b: &i32| a < b;
+        assert_eq!(queue.first_ready(ready, before), Some(&1));
+        assert_eq!(queue.pop_ready(ready, before), Some(1));
+        assert_eq!(queue.pop_ready(ready, before), Some(3));
+        //the ones behind a task that is not ready wait
+        assert_eq!(queue.pop_ready(ready, before), None);
+        assert_eq!(queue.len(), 2);
+    }
+}
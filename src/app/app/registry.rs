@@ -0,0 +1,101 @@
+use super::queue::Queue;
+use crate::app::Task;
+use spoler_proto::{validate_queue_name, QueueConfig};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::Mutex;
+
+pub type AppQueue<T> = Arc<Mutex<T>>;
+
+struct NamedQueue<T> {
+    queue: AppQueue<T>,
+    config: QueueConfig,
+}
+
+//the queues by name, shared by every instance of the app, so the queues created or
+//deleted by one connection are seen by all of them
+pub struct QueueRegistry<T> {
+    queues: Arc<RwLock<BTreeMap<String, NamedQueue<T>>>>,
+}
+
+impl<T> QueueRegistry<T>
+where
+    T: Queue<Task>,
+{
+    pub fn new() -> Self {
+        Self {
+            queues: Arc::new(RwLock::new(BTreeMap::new())),
+        }
+    }
+
+    pub fn create(&self, name: &str, config: QueueConfig) -> Result<(), String> {
+        validate_queue_name(name)?;
+        let mut queues = self.queues.write().unwrap();
+        if queues.contains_key(name) {
+            return Err(format!("Queue {} already exists", name));
+        }
+        queues.insert(
+            name.to_string(),
+            NamedQueue {
+                queue: Arc::new(Mutex::new(T::new())),
+                config,
+            },
+        );
+        Ok(())
+    }
+
+    //removes the queue from the registry, the caller deals with its tasks
+    pub fn delete(&self, name: &str) -> Result<(), String> {
+        self.queues
+            .write()
+            .unwrap()
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| not_found(name))
+    }
+
+    pub fn get(&self, name: &str) -> Result<(AppQueue<T>, QueueConfig), String> {
+        self.queues
+            .read()
+            .unwrap()
+            .get(name)
+            .map(|named| (Arc::clone(&named.queue), named.config.clone()))
+            .ok_or_else(|| not_found(name))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.queues.read().unwrap().contains_key(name)
+    }
+
+    //false when the queue was deleted (or deleted and created again) after it was taken
+    //from the registry, the tasks inserted in it would be lost
+    pub fn is_current(&self, name: &str, queue: &AppQueue<T>) -> bool {
+        self.queues
+            .read()
+            .unwrap()
+            .get(name)
+            .is_some_and(|named| Arc::ptr_eq(&named.queue, queue))
+    }
+
+    //every queue with its config, sorted by name
+    pub fn all(&self) -> Vec<(String, AppQueue<T>, QueueConfig)> {
+        self.queues
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, named)| (name.clone(), Arc::clone(&named.queue), named.config.clone()))
+            .collect()
+    }
+}
+
+impl<T> Clone for QueueRegistry<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queues: Arc::clone(&self.queues),
+        }
+    }
+}
+
+fn not_found(name: &str) -> String {
+    format!("Queue {} does not exist", name)
+}
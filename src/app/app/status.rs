@@ -0,0 +1,186 @@
+use crate::app::Task;
+use chrono::{DateTime, Duration, Utc};
+use spoler_proto::{QueueCounters, TaskState, TaskStatus};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+//keeps track of the state of every task id, it's shared by the app instances and the workers.
+//the statuses of the finished tasks are forgotten after the retention
+#[derive(Debug, Clone)]
+pub struct StatusRegistry {
+    statuses: Arc<Mutex<HashMap<String, TaskStatus>>>,
+    counters: Arc<Mutex<HashMap<String, QueueCounters>>>,
+    retention: Duration,
+    //the ids in the order they finished, for forgetting them when the retention ends
+    finished: Arc<Mutex<Finished>>,
+}
+
+type Finished = VecDeque<(DateTime<Utc>, String)>;
+
+impl StatusRegistry {
+    pub fn new(retention_secs: u32) -> Self {
+        Self {
+            statuses: Arc::new(Mutex::new(HashMap::new())),
+            counters: Arc::new(Mutex::new(HashMap::new())),
+            retention: Duration::seconds(retention_secs.into()),
+            finished: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<TaskStatus> {
+        let mut statuses = self.statuses.lock().unwrap();
+        self.forget_before(&mut statuses, Utc::now() - self.retention);
+        statuses.get(id).cloned()
+    }
+
+    pub fn counters(&self, queue: &str) -> QueueCounters {
+        self.counters
+            .lock()
+            .unwrap()
+            .get(queue)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn pending(&self, task: &Task) {
+        self.update(task, TaskState::Pending, None);
+    }
+
+    pub fn dispatched(&self, task: &Task) {
+        self.update(task, TaskState::Dispatched, None);
+    }
+
+    pub fn running(&self, task: &Task) {
+        self.update(task, TaskState::Running, None);
+    }
+
+    pub fn succeeded(&self, task: &Task) {
+        self.update(task, TaskState::Succeeded, None);
+    }
+
+    pub fn failed(&self, task: &Task, error: String) {
+        self.update(task, TaskState::Failed, Some(error));
+    }
+
+    //the task failed and is back in its queue, waiting for another run
+    pub fn retrying(&self, task: &Task, error: String) {
+        self.count(&task.queue, TaskState::Failed);
+        self.set(task, TaskState::Pending, Some(error));
+    }
+
+    pub fn cancelled(&self, id: &str) {
+        let now = Utc::now();
+        let mut statuses = self.statuses.lock().unwrap();
+        if let Some(status) = statuses.get_mut(id) {
+            status.state = TaskState::Cancelled;
+            status.updated_at = now.to_rfc3339();
+            self.finish(&mut statuses, id, now);
+        }
+    }
+
+    //a new task was refused because its queue was full, it never gets a status
+    pub fn rejected(&self, queue: &str) {
+        self.counters
+            .lock()
+            .unwrap()
+            .entry(queue.to_string())
+            .or_default()
+            .rejected += 1;
+    }
+
+    //the counters of a deleted queue, a new queue with its name starts from zero
+    pub fn remove_queue(&self, queue: &str) {
+        self.counters.lock().unwrap().remove(queue);
+    }
+
+    pub fn dropped(&self, task: &Task) {
+        self.update(
+            task,
+            TaskState::Dropped,
+            Some(String::from("Dropped, the queue was full")),
+        );
+    }
+
+    fn update(&self, task: &Task, state: TaskState, error: Option<String>) {
+        self.count(&task.queue, state);
+        self.set(task, state, error);
+    }
+
+    fn count(&self, queue: &str, state: TaskState) {
+        let mut counters = self.counters.lock().unwrap();
+        let counters = counters.entry(queue.to_string()).or_default();
+        match state {
+            TaskState::Pending => counters.enqueued += 1,
+            TaskState::Dispatched => counters.dispatched += 1,
+            TaskState::Failed => counters.failed += 1,
+            TaskState::Dropped => counters.dropped += 1,
+            _ => (),
+        }
+    }
+
+    fn set(&self, task: &Task, state: TaskState, error: Option<String>) {
+        let finished_at = Utc::now();
+        let now = finished_at.to_rfc3339();
+        let mut statuses = self.statuses.lock().unwrap();
+        let status = statuses
+            .entry(task.id.clone())
+            .or_insert_with(|| TaskStatus {
+                id: task.id.clone(),
+                queue: task.queue.clone(),
+                state,
+                pending_at: None,
+                dispatched_at: None,
+                started_at: None,
+                finished_at: None,
+                updated_at: now.clone(),
+                last_error: None,
+            });
+
+        status.queue = task.queue.clone();
+        status.state = state;
+        status.updated_at = now.clone();
+        match state {
+            TaskState::Pending => status.pending_at = Some(now),
+            TaskState::Dispatched => status.dispatched_at = Some(now),
+            TaskState::Running => status.started_at = Some(now),
+            TaskState::Succeeded | TaskState::Failed => status.finished_at = Some(now),
+            TaskState::Cancelled => (),
+            TaskState::Dropped => status.finished_at = Some(now),
+        }
+        if error.is_some() {
+            status.last_error = error;
+        }
+        if matches!(
+            state,
+            TaskState::Succeeded | TaskState::Failed | TaskState::Dropped
+        ) {
+            self.finish(&mut statuses, &task.id, finished_at);
+        }
+    }
+
+    //records that the task finished, and forgets the tasks that finished before the retention
+    fn finish(&self, statuses: &mut HashMap<String, TaskStatus>, id: &str, at: DateTime<Utc>) {
+        self.finished
+            .lock()
+            .unwrap()
+            .push_back((at, id.to_string()));
+        self.forget_before(statuses, at - self.retention);
+    }
+
+    fn forget_before(&self, statuses: &mut HashMap<String, TaskStatus>, limit: DateTime<Utc>) {
+        let mut finished = self.finished.lock().unwrap();
+        while let Some((finished_at, _)) = finished.front() {
+            if *finished_at > limit {
+                return;
+            }
+            let (finished_at, id) = finished.pop_front().unwrap();
+            //the id may have been enqueued again later, then it stays
+            if statuses
+                .get(&id)
+                .is_some_and(|status| status.updated_at == finished_at.to_rfc3339())
+            {
+                statuses.remove(&id);
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::app::Task;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+
+//how many due tasks can be waiting to be written to a subscribed connection
+const SUBSCRIPTION_BUFFER: usize = 100;
+
+//the connections that consume the due tasks of a queue themselves, instead of the local worker
+#[derive(Debug, Clone, Default)]
+pub struct Subscriptions {
+    subscribers: Arc<Mutex<HashMap<String, Vec<Sender<Task>>>>>,
+    //for spreading the tasks between the subscribers of the same queue
+    next: Arc<AtomicUsize>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //subscribes to the queues, the due tasks of them are received from the returned receiver
+    //the subscription ends when the receiver is dropped
+    pub fn subscribe(&self, queues: &[String]) -> Receiver<Task> {
+        let (sender, receiver) = channel(SUBSCRIPTION_BUFFER);
+        let mut subscribers = self.subscribers.lock().unwrap();
+        for queue in queues {
+            subscribers
+                .entry(queue.clone())
+                .or_default()
+                .push(sender.clone());
+        }
+        receiver
+    }
+
+    //the subscribers of a deleted queue, they don't get the tasks of a new queue with its name
+    pub fn remove_queue(&self, queue: &str) {
+        self.subscribers.lock().unwrap().remove(queue);
+    }
+
+    //sends the task to one of the subscribers of its queue, without waiting for them.
+    //the task is given back when nobody is subscribed to the queue, or all of them are busy
+    pub fn push(&self, task: Task) -> Option<Task> {
+        let senders = {
+            let mut subscribers = self.subscribers.lock().unwrap();
+            match subscribers.get_mut(&task.queue) {
+                Some(senders) => {
+                    senders.retain(|sender| !sender.is_closed());
+                    senders.clone()
+                }
+                None => return Some(task),
+            }
+        };
+        if senders.is_empty() {
+            return Some(task);
+        }
+
+        //try all the subscribers, starting from a different one every time
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut task = task;
+        for i in 0..senders.len() {
+            match senders[(start + i) % senders.len()].try_send(task) {
+                Ok(()) => return None,
+                Err(TrySendError::Full(t)) | Err(TrySendError::Closed(t)) => task = t,
+            }
+        }
+        //a subscriber that stops reading must not stop the dispatch of the other queues
+        Some(task)
+    }
+}
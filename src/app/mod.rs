@@ -1,3 +1,7 @@
+#[allow(clippy::module_inception)]
 mod app;
 
-pub use app::{App, Heap, Task, TaskSettings, TaskType};
+pub use app::{
+    App, Command, Deduplication, Heap, Lifecycle, Limits, Overflow, QueueConfig, Reply,
+    ReplyStatus, Response, StatusRegistry, Task, TaskChanges, Tokens,
+};
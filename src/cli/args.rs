@@ -0,0 +1,319 @@
+use crate::app::Overflow;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+//running spoler without a subcommand serves, as it always did
+#[derive(Debug, Parser)]
+#[command(
+    name = "spoler",
+    version,
+    about = "Schedules tasks and runs them when they are due"
+)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+    #[command(flatten)]
+    pub serve: ServeArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CliCommand {
+    /// Run the server (the default without a subcommand)
+    Serve(ServeArgs),
+    /// Enqueue a task in a running server
+    Enqueue(EnqueueArgs),
+    /// Cancel a pending task
+    Cancel {
+        #[command(flatten)]
+        server: ServerArgs,
+        /// Id of the task
+        id: String,
+    },
+    /// Change the eta, the repeat interval or the payload of a pending task
+    Reschedule(RescheduleArgs),
+    /// Dispatch a pending task now, a recurring task keeps its schedule
+    RunNow {
+        #[command(flatten)]
+        server: ServerArgs,
+        /// Id of the task
+        id: String,
+    },
+    /// Show the status of a task
+    Status {
+        #[command(flatten)]
+        server: ServerArgs,
+        /// Id of the task
+        id: String,
+    },
+    /// List the pending tasks of a queue, in eta order
+    List {
+        #[command(flatten)]
+        server: ServerArgs,
+        /// Queue to list
+        #[arg(long, default_value = "0")]
+        queue: String,
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// Show the length and the counters of every queue
+    Stats {
+        #[command(flatten)]
+        server: ServerArgs,
+    },
+    /// Run the tasks of remote queues, leasing them from a running server
+    Worker(WorkerArgs),
+    /// Create a queue in a running server
+    CreateQueue {
+        #[command(flatten)]
+        server: ServerArgs,
+        /// Name of the queue
+        name: String,
+        /// How many pending tasks the queue holds, 0 is unbounded, the default of the server without it
+        #[arg(long)]
+        capacity: Option<usize>,
+        /// What to do with new tasks when the queue is full, the default of the server without it
+        #[arg(long)]
+        overflow: Option<Overflow>,
+        /// Only remote workers consume the queue
+        #[arg(long)]
+        remote: bool,
+    },
+    /// Delete a queue of a running server
+    DeleteQueue {
+        #[command(flatten)]
+        server: ServerArgs,
+        /// Name of the queue
+        name: String,
+        /// Cancel the pending tasks of the queue, otherwise it must be empty
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum WorkerApp {
+    /// Tasks run as methods of a python app
+    Python,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Host of the tcp server and the http api
+    #[arg(long, default_value = "localhost")]
+    pub host: String,
+    /// Port of the tcp server
+    #[arg(long, default_value_t = 8080)]
+    pub port: u16,
+    /// Number of queues named by their position, 3 creates the queues 0, 1 and 2
+    #[arg(long, default_value_t = 1)]
+    pub queues: u32,
+    /// More queues to create at startup, by name, like mail,reports
+    #[arg(long, value_delimiter = ',')]
+    pub queue_names: Vec<String>,
+    /// Port of the http api, it's disabled without it
+    #[arg(long)]
+    pub http_port: Option<u16>,
+    /// Port of the websocket endpoint, it's disabled without it
+    #[arg(long)]
+    pub ws_port: Option<u16>,
+    /// Port of the redis compatible (resp) listener, it's disabled without it
+    #[arg(long)]
+    pub resp_port: Option<u16>,
+    /// Queues consumed only by remote workers, like 1,2
+    #[arg(long, value_delimiter = ',')]
+    pub remote_queues: Vec<String>,
+    /// Tokens and their permissions, connections must authenticate with one of them
+    #[arg(long)]
+    pub auth_file: Option<String>,
+    /// Certificate of the tcp server, for serving over tls
+    #[arg(long, requires = "tls_key")]
+    pub tls_cert: Option<String>,
+    /// Private key of the tls certificate
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<String>,
+    /// The clients must present a certificate signed by this ca
+    #[arg(long, requires = "tls_cert")]
+    pub tls_client_ca: Option<String>,
+    /// Also serve the protocol in this unix socket
+    #[arg(long)]
+    pub unix_socket: Option<String>,
+    /// Octal permissions of the unix socket file, like 660
+    #[arg(long, requires = "unix_socket", value_parser = parse_mode)]
+    pub unix_socket_mode: Option<u32>,
+    /// Seconds to wait for the running tasks when shutting down or draining
+    #[arg(long, default_value_t = 30)]
+    pub shutdown_timeout: u64,
+    /// Keeps the pending tasks in this file between runs
+    #[arg(long)]
+    pub snapshot_file: Option<String>,
+    /// Capacity of every queue, 0 is unbounded
+    #[arg(long, default_value_t = 0)]
+    pub queue_capacity: usize,
+    /// Capacity of some queues, like 0:100,mail:50
+    #[arg(long, value_delimiter = ',', value_parser = parse_capacity)]
+    pub queue_capacities: Vec<(String, usize)>,
+    /// What to do with new tasks when their queue is full: reject, block or drop-oldest
+    #[arg(long, default_value = "reject")]
+    pub overflow: Overflow,
+    /// Seconds the key of an accepted task is remembered, enqueuing it again meanwhile is a duplicate. 0, the default, disables it
+    #[arg(long, default_value_t = 0)]
+    pub dedup_window: u32,
+    /// How many due tasks can wait for the local worker
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub dispatch_capacity: u32,
+    /// Seconds the status of a finished task is kept, for the status command
+    #[arg(long, default_value_t = 3600)]
+    pub status_retention: u32,
+    /// Kind of application of the local worker
+    #[arg(long, value_enum, requires = "project_path")]
+    pub app: Option<WorkerApp>,
+    /// File of the python app, with its SpoolerApp class
+    #[arg(long)]
+    pub project_path: Option<String>,
+}
+
+//where the client subcommands find the server
+#[derive(Debug, Args)]
+pub struct ServerArgs {
+    /// Address of the spoler tcp server
+    #[arg(long, default_value = "localhost:8080")]
+    pub server: String,
+    /// Token, for servers running with --auth-file
+    #[arg(long)]
+    pub token: Option<String>,
+    /// Connect with tls, trusting the server certificates signed by this ca
+    #[arg(long)]
+    pub tls_ca: Option<String>,
+    /// Certificate of the client, for servers running with --tls-client-ca
+    #[arg(long, requires_all = ["tls_ca", "tls_key"])]
+    pub tls_cert: Option<String>,
+    /// Private key of the client certificate
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<String>,
+    /// Name the server certificate is checked against, and sent as sni. The host of --server without it
+    #[arg(long, requires = "tls_ca")]
+    pub tls_server_name: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct EnqueueArgs {
+    #[command(flatten)]
+    pub server: ServerArgs,
+    /// Id of the task
+    pub id: String,
+    #[arg(long, default_value = "0")]
+    pub queue: String,
+    /// When the task is due, like 2030-01-01T10:00:00Z, or 2030-01-01T10:00:00 in the time zone of the task.
+    /// It's due now without it
+    #[arg(long, conflicts_with = "delay", value_parser = parse_eta)]
+    pub eta: Option<String>,
+    /// Seconds from now until the task is due
+    #[arg(long)]
+    pub delay: Option<u32>,
+    #[arg(long)]
+    pub payload: Option<String>,
+    /// Among the tasks due at the same time, the ones with the highest priority run first
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+    pub priority: i32,
+    /// Iana time zone of the local etas, the repetitions and the cron times, like Europe/Madrid. Utc without it
+    #[arg(long)]
+    pub timezone: Option<String>,
+    /// The task calls this url
+    #[arg(long)]
+    pub url: Option<String>,
+    /// Method of the request to the url
+    #[arg(long, default_value = "POST", requires = "url")]
+    pub method: String,
+    /// Header of the request to the url, like content-type:application/json
+    #[arg(long = "header", requires = "url", value_parser = parse_header)]
+    pub headers: Vec<(String, String)>,
+    /// The task calls this method of the python app
+    #[arg(long, conflicts_with = "url")]
+    pub python: Option<String>,
+    /// Seconds between the repetitions of the task
+    #[arg(long, group = "schedule")]
+    pub repeat_every: Option<u32>,
+    /// Cron expression of the repetitions, like "0 9 * * 1-5", it's first due at its next time without eta
+    #[arg(long, group = "schedule")]
+    pub cron: Option<String>,
+    /// How many times the task repeats, forever without it
+    #[arg(long, requires = "schedule")]
+    pub repetitions: Option<i32>,
+    /// Retries with the same key are inserted once, the id is the key without it
+    #[arg(long)]
+    pub idempotency_key: Option<String>,
+    /// Replace the pending task enqueued before with the same key
+    #[arg(long)]
+    pub upsert: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RescheduleArgs {
+    #[command(flatten)]
+    pub server: ServerArgs,
+    /// Id of the task
+    pub id: String,
+    /// The new eta, like 2030-01-01T10:00:00Z, or 2030-01-01T10:00:00 in the time zone of the task
+    #[arg(long, conflicts_with = "delay", value_parser = parse_eta)]
+    pub eta: Option<String>,
+    /// Seconds from now until the task is due
+    #[arg(long)]
+    pub delay: Option<u32>,
+    /// The new seconds between the repetitions of the task, 0 stops them
+    #[arg(long, conflicts_with = "cron")]
+    pub repeat_every: Option<u32>,
+    /// The new cron expression of the repetitions
+    #[arg(long)]
+    pub cron: Option<String>,
+    #[arg(long)]
+    pub payload: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct WorkerArgs {
+    #[command(flatten)]
+    pub server: ServerArgs,
+    /// Queues to lease the tasks from, like 1,mail
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub queues: Vec<String>,
+    /// How many tasks run at the same time
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub concurrency: u32,
+    /// Seconds a leased task can run before going back to its queue, heartbeats extend it
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(2..))]
+    pub visibility_timeout: u32,
+    /// Milliseconds between leases when the queues are empty
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub poll_interval_ms: u64,
+}
+
+fn parse_mode(raw: &str) -> Result<u32, String> {
+    u32::from_str_radix(raw, 8).map_err(|_| format!("{} is not an octal mode", raw))
+}
+
+fn parse_capacity(raw: &str) -> Result<(String, usize), String> {
+    let invalid = || format!("{} is not a queue:capacity pair", raw);
+    let (queue, capacity) = raw.rsplit_once(':').ok_or_else(invalid)?;
+    Ok((
+        queue.trim().to_string(),
+        capacity.trim().parse().map_err(|_| invalid())?,
+    ))
+}
+
+//an eta with its offset or a local time, the server reads the local ones in the time zone of the task
+fn parse_eta(raw: &str) -> Result<String, String> {
+    match raw.parse::<DateTime<Utc>>().is_ok() || raw.parse::<NaiveDateTime>().is_ok() {
+        true => Ok(raw.to_string()),
+        false => Err(format!("{} is not a valid eta", raw)),
+    }
+}
+
+fn parse_header(raw: &str) -> Result<(String, String), String> {
+    let (name, value) = raw
+        .split_once(':')
+        .ok_or_else(|| format!("{} is not a name:value header", raw))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
@@ -0,0 +1,187 @@
+use super::args::{EnqueueArgs, RescheduleArgs, ServerArgs};
+use crate::app::{Overflow, QueueConfig};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::json;
+use spoler_client::{Client, TaskBuilder, TaskChanges, TlsOptions};
+
+//the client subcommands print the replies of the server as json, and fail with its reason
+
+pub async fn enqueue(args: EnqueueArgs) -> Result<(), String> {
+    let mut task = TaskBuilder::new(args.id.clone())
+        .queue(args.queue)
+        .priority(args.priority);
+    if let Some(timezone) = args.timezone {
+        task = task.timezone(timezone);
+    }
+    if let Some(eta) = args.eta {
+        task = match eta.parse::<DateTime<Utc>>() {
+            Ok(eta) => task.eta(eta),
+            Err(_) => task.local_eta(eta.parse().map_err(|_| format!("Invalid eta: {}", eta))?),
+        };
+    }
+    if let Some(delay) = args.delay {
+        task = task.eta(Utc::now() + Duration::seconds(delay.into()));
+    }
+    if let Some(payload) = args.payload {
+        task = task.payload(payload);
+    }
+    if let Some(url) = args.url {
+        task = task.request(args.method, url);
+    }
+    for (name, value) in args.headers {
+        task = task.header(name, value);
+    }
+    if let Some(executor_ref) = args.python {
+        task = task.python(executor_ref);
+    }
+    if let Some(seconds) = args.repeat_every {
+        task = task.repeat_every(seconds);
+    }
+    if let Some(cron) = args.cron {
+        task = task.cron(cron);
+    }
+    if let Some(repetitions) = args.repetitions {
+        task = task.repetitions(repetitions);
+    }
+    if let Some(key) = args.idempotency_key {
+        task = task.idempotency_key(key);
+    }
+    if args.upsert {
+        task = task.upsert();
+    }
+
+    let mut client = connect(&args.server).await?;
+    let reply = client
+        .enqueue(&task.build())
+        .await
+        .map_err(|e| e.to_string())?;
+    print_json(&reply)
+}
+
+pub async fn cancel(server: ServerArgs, id: String) -> Result<(), String> {
+    let mut client = connect(&server).await?;
+    let removed = client.cancel(&id).await.map_err(|e| e.to_string())?;
+    if removed == 0 {
+        return Err(format!("No pending task with id {}", id));
+    }
+    print_json(&json!({ "id": id, "removed": removed }))
+}
+
+pub async fn reschedule(args: RescheduleArgs) -> Result<(), String> {
+    let eta = match (args.eta, args.delay) {
+        (Some(eta), _) => Some(eta),
+        (None, Some(delay)) => Some((Utc::now() + Duration::seconds(delay.into())).to_rfc3339()),
+        (None, None) => None,
+    };
+    let changes = TaskChanges {
+        eta,
+        repeat_interval: args.repeat_every,
+        cron: args.cron,
+        payload: args.payload,
+    };
+    let mut client = connect(&args.server).await?;
+    let updated = client
+        .reschedule(&args.id, changes)
+        .await
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err(format!("No pending task with id {}", args.id));
+    }
+    print_json(&json!({ "id": args.id, "updated": updated }))
+}
+
+pub async fn run_now(server: ServerArgs, id: String) -> Result<(), String> {
+    let mut client = connect(&server).await?;
+    let updated = client.run_now(&id).await.map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err(format!("No pending task with id {}", id));
+    }
+    print_json(&json!({ "id": id, "updated": updated }))
+}
+
+pub async fn status(server: ServerArgs, id: String) -> Result<(), String> {
+    let mut client = connect(&server).await?;
+    match client.status(&id).await.map_err(|e| e.to_string())? {
+        Some(status) => print_json(&status),
+        None => Err(format!("Spoler never saw a task with id {}", id)),
+    }
+}
+
+pub async fn list(
+    server: ServerArgs,
+    queue: String,
+    offset: usize,
+    limit: usize,
+) -> Result<(), String> {
+    let mut client = connect(&server).await?;
+    let reply = client
+        .list(&queue, offset, limit)
+        .await
+        .map_err(|e| e.to_string())?;
+    print_json(&json!({
+        "queue": reply.queue,
+        "total": reply.total,
+        "offset": reply.offset,
+        "tasks": reply.tasks,
+    }))
+}
+
+pub async fn stats(server: ServerArgs) -> Result<(), String> {
+    let mut client = connect(&server).await?;
+    let stats = client.stats().await.map_err(|e| e.to_string())?;
+    print_json(&stats)
+}
+
+pub async fn create_queue(
+    server: ServerArgs,
+    name: String,
+    capacity: Option<usize>,
+    overflow: Option<Overflow>,
+    remote: bool,
+) -> Result<(), String> {
+    let mut client = connect(&server).await?;
+    let config = QueueConfig {
+        capacity,
+        overflow,
+        remote,
+    };
+    let reply = client
+        .create_queue(&name, config)
+        .await
+        .map_err(|e| e.to_string())?;
+    print_json(&json!({ "queue": reply.queue, "config": reply.config }))
+}
+
+pub async fn delete_queue(server: ServerArgs, name: String, force: bool) -> Result<(), String> {
+    let mut client = connect(&server).await?;
+    let removed = client
+        .delete_queue(&name, force)
+        .await
+        .map_err(|e| e.to_string())?;
+    print_json(&json!({ "queue": name, "removed": removed }))
+}
+
+pub async fn connect(server: &ServerArgs) -> Result<Client, String> {
+    let mut builder = Client::builder(server.server.clone());
+    if let Some(token) = &server.token {
+        builder = builder.token(token.clone());
+    }
+    if let Some(ca) = &server.tls_ca {
+        builder = builder.tls(TlsOptions {
+            ca: ca.clone(),
+            identity: server.tls_cert.clone().zip(server.tls_key.clone()),
+            server_name: server.tls_server_name.clone(),
+        });
+    }
+    builder
+        .connect()
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", server.server, e))
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    println!("{}", json);
+    Ok(())
+}
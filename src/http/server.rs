@@ -0,0 +1,257 @@
+use crate::app::{
+    App, Command, Heap, QueueConfig, Reply, ReplyStatus, Response, Task, TaskChanges,
+};
+use crate::listener;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, StatusCode};
+use percent_encoding::percent_decode_str;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+type HttpResponse = hyper::Response<Body>;
+
+//Serves the http/json api, it shares the queues with the tcp connections
+//
+//POST   /tasks                     enqueue a task, the body is the same task json as in tcp
+//POST   /tasks/batch               enqueue a json array of tasks, all of them or none
+//GET    /tasks/{id}                status of a task
+//DELETE /tasks/{id}                cancel a task
+//PATCH  /tasks/{id}                change the eta, repeat_interval, cron or payload of a pending task
+//POST   /tasks/{id}/run            dispatch a pending task now, recurring tasks keep their schedule
+//GET    /queues                    stats of every queue
+//PUT    /queues/{queue}            create a queue, the body is its config as in the create_queue command
+//DELETE /queues/{queue}            delete a queue, ?force=true cancels its pending tasks
+//GET    /queues/{queue}/tasks      pending tasks of a queue, ?offset=&limit= for paging
+//GET    /queues/{queue}/peek       next due task of a queue
+//
+//with authentication enabled, requests carry the token as "authorization: Bearer {token}".
+//with tls enabled the api is served over https, as the tcp server
+pub async fn serve(app: App<Heap<Task>>, listener: TcpListener, tls_acceptor: Option<TlsAcceptor>) {
+    let lifecycle = app.lifecycle.clone();
+    listener::accept(
+        "Http api",
+        listener,
+        tls_acceptor,
+        lifecycle,
+        move |stream, _| serve_connection(app.clone(), stream),
+    )
+    .await;
+}
+
+//serves the requests of a connection, a draining node closes it after the running request
+async fn serve_connection<S>(app: App<Heap<Task>>, stream: S)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let lifecycle = app.lifecycle.clone();
+    let service = service_fn(move |request| handle_request(app.clone(), request));
+    let connection = Http::new().serve_connection(stream, service);
+    tokio::pin!(connection);
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = lifecycle.draining() => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Http connection error: {}", e);
+    }
+}
+
+async fn handle_request(
+    mut app: App<Heap<Task>>,
+    request: Request<Body>,
+) -> Result<HttpResponse, Infallible> {
+    let token = request
+        .headers()
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or("");
+    if !app.authenticate(token) {
+        return Ok(error(StatusCode::UNAUTHORIZED, "Authentication required"));
+    }
+
+    let method = request.method().clone();
+    let path: Vec<String> = request
+        .uri()
+        .path()
+        .trim_matches('/')
+        .split('/')
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().to_string())
+        .collect();
+    let segments: Vec<&str> = path.iter().map(|segment| segment.as_str()).collect();
+    let query = parse_query(request.uri().query());
+
+    let response = match (&method, segments.as_slice()) {
+        (&Method::POST, ["tasks"]) => {
+            let body = match hyper::body::to_bytes(request.into_body()).await {
+                Ok(body) => body,
+                Err(e) => return Ok(error(StatusCode::BAD_REQUEST, e.to_string())),
+            };
+            match serde_json::from_slice::<Task>(&body) {
+                Ok(task) => Response::Task(app.enqueue(task).await),
+                Err(e) => {
+                    Response::Task(Reply::rejected(None, None, format!("Invalid task: {}", e)))
+                }
+            }
+        }
+        (&Method::POST, ["tasks", "batch"]) => {
+            let body = match hyper::body::to_bytes(request.into_body()).await {
+                Ok(body) => body,
+                Err(e) => return Ok(error(StatusCode::BAD_REQUEST, e.to_string())),
+            };
+            match serde_json::from_slice::<Vec<Value>>(&body) {
+                Ok(tasks) => app.handle_command(Command::Batch { tasks }).await,
+                Err(e) => {
+                    return Ok(error(
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid batch: {}", e),
+                    ))
+                }
+            }
+        }
+        (&Method::GET, ["tasks", id]) => {
+            app.handle_command(Command::Status { id: id.to_string() })
+                .await
+        }
+        (&Method::DELETE, ["tasks", id]) => {
+            app.handle_command(Command::Cancel { id: id.to_string() })
+                .await
+        }
+        (&Method::PATCH, ["tasks", id]) => {
+            let body = match hyper::body::to_bytes(request.into_body()).await {
+                Ok(body) => body,
+                Err(e) => return Ok(error(StatusCode::BAD_REQUEST, e.to_string())),
+            };
+            match serde_json::from_slice::<TaskChanges>(&body) {
+                Ok(changes) => {
+                    let id = id.to_string();
+                    app.handle_command(Command::Reschedule { id, changes })
+                        .await
+                }
+                Err(e) => {
+                    return Ok(error(
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid changes: {}", e),
+                    ))
+                }
+            }
+        }
+        (&Method::POST, ["tasks", id, "run"]) => {
+            app.handle_command(Command::RunNow { id: id.to_string() })
+                .await
+        }
+        (&Method::GET, ["queues"]) => app.handle_command(Command::Stats).await,
+        (&Method::PUT, ["queues", queue]) => {
+            let body = match hyper::body::to_bytes(request.into_body()).await {
+                Ok(body) => body,
+                Err(e) => return Ok(error(StatusCode::BAD_REQUEST, e.to_string())),
+            };
+            //without body the queue takes the defaults of the node
+            let config = match body.is_empty() {
+                true => Ok(QueueConfig::default()),
+                false => serde_json::from_slice::<QueueConfig>(&body),
+            };
+            match config {
+                Ok(config) => {
+                    let name = queue.to_string();
+                    app.handle_command(Command::CreateQueue { name, config })
+                        .await
+                }
+                Err(e) => {
+                    let reason = format!("Invalid queue config: {}", e);
+                    return Ok(error(StatusCode::BAD_REQUEST, reason));
+                }
+            }
+        }
+        (&Method::DELETE, ["queues", queue]) => {
+            let force = query.get("force").is_some_and(|force| force == "true");
+            let name = queue.to_string();
+            app.handle_command(Command::DeleteQueue { name, force })
+                .await
+        }
+        (&Method::GET, ["queues", queue, "tasks"]) => {
+            let command = match (
+                query_usize(&query, "offset", 0),
+                query_usize(&query, "limit", 50),
+            ) {
+                (Ok(offset), Ok(limit)) => Command::List {
+                    queue: queue.to_string(),
+                    offset,
+                    limit,
+                },
+                _ => return Ok(error(StatusCode::BAD_REQUEST, "Invalid paging")),
+            };
+            app.handle_command(command).await
+        }
+        (&Method::GET, ["queues", queue, "peek"]) => {
+            let queue = Some(queue.to_string());
+            app.handle_command(Command::Peek { queue }).await
+        }
+        _ => return Ok(error(StatusCode::NOT_FOUND, "Not found")),
+    };
+
+    Ok(json(status_code(&response), response.to_line()))
+}
+
+//the http status that best describes the response of the app
+fn status_code(response: &Response) -> StatusCode {
+    match response {
+        Response::Task(reply) if reply.full => StatusCode::TOO_MANY_REQUESTS,
+        Response::Task(reply) if reply.reason.is_some() => StatusCode::BAD_REQUEST,
+        Response::Task(_) => StatusCode::CREATED,
+        Response::Batch(reply) if reply.tasks.iter().any(|task| task.full) => {
+            StatusCode::TOO_MANY_REQUESTS
+        }
+        Response::Batch(reply) if reply.status == ReplyStatus::Rejected => StatusCode::BAD_REQUEST,
+        Response::Batch(_) => StatusCode::CREATED,
+        Response::CreateQueue(_) => StatusCode::CREATED,
+        Response::Cancel(reply) if reply.removed == 0 => StatusCode::NOT_FOUND,
+        Response::Reschedule(reply) if reply.updated == 0 => StatusCode::NOT_FOUND,
+        Response::Status(reply) if reply.task.is_none() => StatusCode::NOT_FOUND,
+        Response::Error(reply) if reply.forbidden => StatusCode::FORBIDDEN,
+        Response::Error(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::OK,
+    }
+}
+
+fn parse_query(query: Option<&str>) -> HashMap<String, String> {
+    query
+        .unwrap_or("")
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| {
+            (
+                key.to_string(),
+                percent_decode_str(value).decode_utf8_lossy().to_string(),
+            )
+        })
+        .collect()
+}
+
+fn query_usize(query: &HashMap<String, String>, key: &str, default: usize) -> Result<usize, ()> {
+    match query.get(key) {
+        Some(value) => value.parse().map_err(|_| ()),
+        None => Ok(default),
+    }
+}
+
+fn json(status: StatusCode, body: String) -> HttpResponse {
+    hyper::Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body))
+        .expect("Valid http response")
+}
+
+fn error(status: StatusCode, reason: impl ToString) -> HttpResponse {
+    let body = serde_json::json!({ "status": "rejected", "reason": reason.to_string() });
+    json(status, format!("{}\n", body))
+}
@@ -0,0 +1,57 @@
+use crate::app::Lifecycle;
+use std::future::Future;
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+//the tcp stream of a connection, or the tls stream over it
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Stream for S {}
+
+//accepts the connections of a listener until the node drains, and serves each one in its own task.
+//with tls, every connection makes the handshake before being served
+pub async fn accept<F, Fut>(
+    name: &str,
+    listener: TcpListener,
+    tls_acceptor: Option<TlsAcceptor>,
+    lifecycle: Lifecycle,
+    serve: F,
+) where
+    F: Fn(Box<dyn Stream>, SocketAddr) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    println!(
+        "{} listening on: {}",
+        name,
+        listener
+            .local_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_default()
+    );
+    loop {
+        //a draining node drops the listener, so new connections are refused
+        let (socket, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    eprintln!("{} failed to accept a connection: {}", name, e);
+                    continue;
+                }
+            },
+            _ = lifecycle.draining() => return,
+        };
+        let tls_acceptor = tls_acceptor.clone();
+        let serve = serve.clone();
+        tokio::task::spawn(async move {
+            match tls_acceptor {
+                Some(acceptor) => match acceptor.accept(socket).await {
+                    Ok(stream) => serve(Box::new(stream), peer).await,
+                    Err(e) => eprintln!("Tls handshake with {} failed: {}", peer, e),
+                },
+                None => serve(Box::new(socket), peer).await,
+            }
+        });
+    }
+}
@@ -1,62 +1,317 @@
 mod app;
+mod cli;
+mod http;
+mod listener;
+mod resp;
+mod tls;
 mod utils;
+mod websocket;
 mod worker;
 
-use app::{App, Heap, Task, TaskType};
+use app::{App, Deduplication, Heap, Lifecycle, Limits, QueueConfig, StatusRegistry, Task, Tokens};
+use clap::Parser;
+use cli::{Cli, CliCommand, ServeArgs, WorkerApp, WorkerArgs};
 use std::collections::HashMap;
-use std::env;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::sync::mpsc::channel;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::mpsc::Sender;
-use worker::AsyncWorker;
+use worker::{AsyncWorker, RemoteWorker};
 
 #[tokio::main]
-async fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    //get the application settings
-    let app_settings: HashMap<String, String> = utils::get_app_settings(args);
-
-    let port: usize =
-        utils::get_usize_from_settings(&app_settings, "--port".to_string(), "8080".to_string());
-    let host: String = utils::get_string_from_settings(
-        &app_settings,
-        "--host".to_string(),
-        "localhost".to_string(),
-    );
+async fn main() -> ExitCode {
+    //bad arguments exit with the usage error of clap
+    let cli = Cli::parse();
 
+    let result = match cli.command {
+        None => serve(cli.serve).await,
+        Some(CliCommand::Serve(args)) => serve(args).await,
+        Some(CliCommand::Enqueue(args)) => cli::enqueue(args).await,
+        Some(CliCommand::Cancel { server, id }) => cli::cancel(server, id).await,
+        Some(CliCommand::Reschedule(args)) => cli::reschedule(args).await,
+        Some(CliCommand::RunNow { server, id }) => cli::run_now(server, id).await,
+        Some(CliCommand::Status { server, id }) => cli::status(server, id).await,
+        Some(CliCommand::List {
+            server,
+            queue,
+            offset,
+            limit,
+        }) => cli::list(server, queue, offset, limit).await,
+        Some(CliCommand::Stats { server }) => cli::stats(server).await,
+        Some(CliCommand::Worker(args)) => work(args).await,
+        Some(CliCommand::CreateQueue {
+            server,
+            name,
+            capacity,
+            overflow,
+            remote,
+        }) => cli::create_queue(server, name, capacity, overflow, remote).await,
+        Some(CliCommand::DeleteQueue {
+            server,
+            name,
+            force,
+        }) => cli::delete_queue(server, name, force).await,
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+//runs the server, it only returns when it fails to start
+async fn serve(args: ServeArgs) -> Result<(), String> {
     //create the tcp listener
-    let listener = TcpListener::bind(format!("{}:{}", host, port))
+    let listener = TcpListener::bind((args.host.as_str(), args.port))
         .await
-        .expect("Failed to bind to tcp port");
+        .map_err(|e| format!("Failed to bind to {}:{}: {}", args.host, args.port, e))?;
+
+    //with tls, every connection makes the handshake before being served
+    let tls_acceptor = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => {
+            Some(tls::get_acceptor(cert, key, args.tls_client_ca.as_deref())?)
+        }
+        _ => None,
+    };
 
-    let (sender, receiver): (Sender<Task>, Receiver<Task>) = channel(100);
+    //how many due tasks can wait for the worker, when it's full they wait in their queues
+    let (sender, receiver): (Sender<Task>, Receiver<Task>) =
+        channel(args.dispatch_capacity as usize);
+
+    //the status of every task, shared by the application and the worker
+    let statuses = StatusRegistry::new(args.status_retention);
 
     //create the application
-    let mut main_app: App<Heap<Task>> = App::new(sender);
+    let mut main_app: App<Heap<Task>> = App::new(sender, statuses.clone());
 
-    // how many queues we are going to have ?
-    let n_queues: usize =
-        utils::get_usize_from_settings(&app_settings, "--queues".to_string(), "1".to_string());
+    //the queues of the command line, --queues 3 creates the queues 0, 1 and 2.
+    //the rest of the queues are created and deleted at runtime with the protocol
+    let names: Vec<String> = (0..args.queues)
+        .map(|queue| queue.to_string())
+        .chain(args.queue_names.iter().cloned())
+        .collect();
+    let capacities: HashMap<&String, usize> = args
+        .queue_capacities
+        .iter()
+        .map(|(queue, capacity)| (queue, *capacity))
+        .collect();
+    let configured = args.remote_queues.iter().chain(capacities.keys().copied());
+    for queue in configured {
+        if !names.contains(queue) {
+            return Err(format!("Queue {} does not exist", queue));
+        }
+    }
+    for name in &names {
+        let config = QueueConfig {
+            capacity: capacities.get(name).copied(),
+            overflow: None,
+            //the queues that only remote workers consume, leasing their tasks
+            remote: args.remote_queues.contains(name),
+        };
+        main_app.create_queue(name, config)?;
+    }
 
-    for _ in 0..n_queues {
-        main_app.add_new_empty_queue();
+    //on shutdown (or drain) spoler waits for the running tasks up to this seconds,
+    //and with a snapshot file the pending tasks are kept between runs
+    main_app.lifecycle = Lifecycle::new(
+        Duration::from_secs(args.shutdown_timeout),
+        args.snapshot_file.clone(),
+    );
+    //the keys of the accepted tasks are remembered for a while, so the retries are not inserted twice
+    main_app.dedup = Deduplication::new(args.dedup_window);
+    if let Some(snapshot_file) = &args.snapshot_file {
+        let restored = main_app.restore_snapshot(snapshot_file).await?;
+        println!("Restored {} tasks from {}", restored, snapshot_file);
     }
 
-    //start a new instance of the app (with same queues) for processing all the clients connections
-    //TODO: create a new thread for this, and not just a new task
+    //the queues are unbounded unless a capacity is given, for all of them or in their config
+    main_app.limits = Limits {
+        default_capacity: args.queue_capacity,
+        overflow: args.overflow,
+    };
+
+    //with an auth file, the connections must authenticate with one of its tokens
+    if let Some(auth_file) = &args.auth_file {
+        main_app.tokens = Some(Arc::new(Tokens::from_file(auth_file)?));
+    }
+
+    //the leases of the remote queues that expire go back to their queues
+    let mut reaper_app = main_app.clone();
     tokio::spawn(async move {
-        loop {
-            let connection = listener.accept().await.unwrap();
-            let mut app = main_app.clone();
-            tokio::task::spawn(async move {
-                app.run(connection).await;
-            });
+        reaper_app.reap_leases().await;
+    });
+
+    //a single instance of the app polls the queues and sends the due tasks to the worker
+    let mut dispatcher_app = main_app.clone();
+    tokio::spawn(async move {
+        dispatcher_app.dispatch().await;
+    });
+
+    //the http api is optional, and listens in the same host as the tcp server, with tls when it's enabled
+    if let Some(http_port) = args.http_port {
+        let http_listener = TcpListener::bind((args.host.as_str(), http_port))
+            .await
+            .map_err(|e| format!("Failed to bind to {}:{}: {}", args.host, http_port, e))?;
+        tokio::spawn(http::serve(
+            main_app.clone(),
+            http_listener,
+            tls_acceptor.clone(),
+        ));
+    }
+
+    //the websocket endpoint carries the same messages as the tcp server, with tls when it's enabled
+    if let Some(ws_port) = args.ws_port {
+        let ws_listener = TcpListener::bind((args.host.as_str(), ws_port))
+            .await
+            .map_err(|e| format!("Failed to bind to {}:{}: {}", args.host, ws_port, e))?;
+        tokio::spawn(websocket::serve(
+            main_app.clone(),
+            ws_listener,
+            tls_acceptor.clone(),
+        ));
+    }
+
+    //redis tooling can enqueue and inspect tasks through the resp listener, with tls when it's enabled
+    if let Some(resp_port) = args.resp_port {
+        let resp_listener = TcpListener::bind((args.host.as_str(), resp_port))
+            .await
+            .map_err(|e| format!("Failed to bind to {}:{}: {}", args.host, resp_port, e))?;
+        tokio::spawn(resp::serve(
+            main_app.clone(),
+            resp_listener,
+            tls_acceptor.clone(),
+        ));
+    }
+
+    //sigterm and sigint drain the node before exiting
+    let shutdown_app = main_app.clone();
+    tokio::spawn(async move {
+        wait_for_shutdown_signal().await;
+        println!("Shutting down, waiting for the running tasks");
+        match shutdown_app
+            .drain(shutdown_app.lifecycle.shutdown_timeout)
+            .await
+        {
+            Ok(reply) => println!(
+                "Drained, {} tasks still running, {:?} tasks in the snapshot",
+                reply.in_flight, reply.snapshot
+            ),
+            Err(e) => eprintln!("Failed to drain: {}", e),
         }
+        std::process::exit(0);
     });
 
+    //local clients can use a unix socket instead of the tcp port,
+    //the filesystem permissions of the socket decide who can connect
+    #[cfg(unix)]
+    if let Some(socket_path) = &args.unix_socket {
+        let listener = bind_unix_socket(socket_path, args.unix_socket_mode)?;
+        let unix_app = main_app.clone();
+        let socket_path = socket_path.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            eprintln!("Failed to accept a unix socket connection: {}", e);
+                            continue;
+                        }
+                    },
+                    _ = unix_app.lifecycle.draining() => return,
+                };
+                let mut app = unix_app.clone();
+                app.trust();
+                let peer = format!("unix:{}", socket_path);
+                tokio::task::spawn(async move {
+                    app.run(socket, peer).await;
+                });
+            }
+        });
+    }
+    #[cfg(not(unix))]
+    if args.unix_socket.is_some() {
+        return Err(String::from(
+            "Unix sockets are not supported in this platform",
+        ));
+    }
+
+    //the worker reports the tasks it finishes
+    let lifecycle = main_app.lifecycle.clone();
+
+    //start a new instance of the app (with same queues) for processing all the clients connections
+    //TODO: create a new thread for this, and not just a new task
+    tokio::spawn(listener::accept(
+        "Tcp server",
+        listener,
+        tls_acceptor,
+        main_app.lifecycle.clone(),
+        move |stream, peer| {
+            let mut app = main_app.clone();
+            async move { app.run(stream, peer.to_string()).await }
+        },
+    ));
+
     // Run the worker async or sync depending on the application type
     // this blocks the thread until the execution is finished.
-    AsyncWorker {}.run(receiver, app_settings.clone());
+    let python_project_path = match args.app {
+        Some(WorkerApp::Python) => args.project_path,
+        None => None,
+    };
+    AsyncWorker {}.run(receiver, python_project_path, statuses, lifecycle);
+    Ok(())
+}
+
+//runs the tasks of remote queues, leasing them from a running server
+async fn work(args: WorkerArgs) -> Result<(), String> {
+    let client = cli::connect(&args.server).await?;
+    RemoteWorker {
+        client,
+        queues: args.queues,
+        concurrency: args.concurrency as usize,
+        visibility_timeout: args.visibility_timeout,
+        poll_interval: Duration::from_millis(args.poll_interval_ms),
+    }
+    .run()
+    .await
+}
+
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for sigterm");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+//binds the unix socket, replacing the one left by a previous run,
+//mode is the permissions of the socket file
+#[cfg(unix)]
+fn bind_unix_socket(path: &str, mode: Option<u32>) -> Result<tokio::net::UnixListener, String> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    if let Ok(metadata) = std::fs::metadata(path) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(path)
+                .map_err(|e| format!("Failed to remove the old unix socket {}: {}", path, e))?;
+        }
+    }
+    let listener = tokio::net::UnixListener::bind(path)
+        .map_err(|e| format!("Failed to bind to unix socket {}: {}", path, e))?;
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .map_err(|e| format!("Failed to set the permissions of {}: {}", path, e))?;
+    }
+    Ok(listener)
 }
@@ -0,0 +1,187 @@
+//the biggest bulk string and array we accept, so a broken client can't make us allocate forever
+const MAX_BULK_LEN: usize = 16 * 1024 * 1024;
+const MAX_ARRAY_LEN: usize = 1024;
+
+//the replies of the resp protocol (version 2)
+#[derive(Debug)]
+pub enum RespValue {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    //none is the null bulk string
+    Bulk(Option<String>),
+    Array(Vec<RespValue>),
+}
+
+impl RespValue {
+    pub fn ok() -> Self {
+        RespValue::Simple(String::from("OK"))
+    }
+
+    pub fn error(reason: impl AsRef<str>) -> Self {
+        //the error of a resp reply is a single line
+        RespValue::Error(format!(
+            "ERR {}",
+            reason.as_ref().replace(['\r', '\n'], " ")
+        ))
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            RespValue::Simple(value) => out.extend(format!("+{}\r\n", value).as_bytes()),
+            RespValue::Error(reason) => out.extend(format!("-{}\r\n", reason).as_bytes()),
+            RespValue::Integer(value) => out.extend(format!(":{}\r\n", value).as_bytes()),
+            RespValue::Bulk(None) => out.extend(b"$-1\r\n"),
+            RespValue::Bulk(Some(value)) => {
+                out.extend(format!("${}\r\n", value.len()).as_bytes());
+                out.extend(value.as_bytes());
+                out.extend(b"\r\n");
+            }
+            RespValue::Array(values) => {
+                out.extend(format!("*{}\r\n", values.len()).as_bytes());
+                for value in values {
+                    value.encode(out);
+                }
+            }
+        }
+    }
+}
+
+//takes the next complete command out of the buffer, if it's there.
+//clients send arrays of bulk strings, and telnet like clients send inline commands
+pub fn next_command(buffer: &mut Vec<u8>) -> Result<Option<Vec<String>>, String> {
+    if buffer.is_empty() {
+        return Ok(None);
+    }
+    if buffer[0] != b'*' {
+        return Ok(read_line(buffer, 0)?.map(|(line, end)| {
+            buffer.drain(..end);
+            line.split_whitespace().map(String::from).collect()
+        }));
+    }
+
+    let (header, mut position) = match read_line(buffer, 0)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let len = parse_len(&header[1..], MAX_ARRAY_LEN)?;
+    let mut args = Vec::with_capacity(len);
+    for _ in 0..len {
+        let (header, start) = match read_line(buffer, position)? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        if !header.starts_with('$') {
+            return Err(String::from("Commands must be arrays of bulk strings"));
+        }
+        let len = parse_len(&header[1..], MAX_BULK_LEN)?;
+        if buffer.len() < start + len + 2 {
+            return Ok(None);
+        }
+        let arg = std::str::from_utf8(&buffer[start..start + len])
+            .map_err(|e| format!("Invalid utf8: {}", e))?;
+        args.push(arg.to_string());
+        position = start + len + 2;
+    }
+    buffer.drain(..position);
+    Ok(Some(args))
+}
+
+//the line that starts at the position, and where the next one starts
+fn read_line(buffer: &[u8], position: usize) -> Result<Option<(String, usize)>, String> {
+    let end = match buffer[position..].iter().position(|b| *b == b'\n') {
+        Some(end) => position + end,
+        None if buffer.len() - position > MAX_BULK_LEN => {
+            return Err(String::from("Line is too long"))
+        }
+        None => return Ok(None),
+    };
+    let line =
+        std::str::from_utf8(&buffer[position..end]).map_err(|e| format!("Invalid utf8: {}", e))?;
+    Ok(Some((line.trim_end_matches('\r').to_string(), end + 1)))
+}
+
+fn parse_len(raw: &str, max: usize) -> Result<usize, String> {
+    match raw.parse::<usize>() {
+        Ok(len) if len <= max => Ok(len),
+        Ok(len) => Err(format!("Length {} is too big", len)),
+        Err(_) => Err(format!("Invalid length: {}", raw)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(args: &[&str]) -> Option<Vec<String>> {
+        Some(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn reads_arrays_of_bulk_strings() {
+        let mut buffer = b"*2\r\n$4\r\nECHO\r\n$5\r\nhello\r\n*1\r\n$4\r\nPING\r\n".to_vec();
+        assert_eq!(next_command(&mut buffer), Ok(command(&["ECHO", "hello"])));
+        assert_eq!(next_command(&mut buffer), Ok(command(&["PING"])));
+        assert_eq!(next_command(&mut buffer), Ok(None));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn reads_inline_commands() {
+        let mut buffer = b"GET  task-1\r\n".to_vec();
+        assert_eq!(next_command(&mut buffer), Ok(command(&["GET", "task-1"])));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_partial_frames() {
+        let frame = b"*2\r\n$3\r\nGET\r\n$6\r\ntask-1\r\n";
+        for len in 1..frame.len() {
+            let mut buffer = frame[..len].to_vec();
+            assert_eq!(next_command(&mut buffer), Ok(None), "{} bytes", len);
+            assert_eq!(buffer, &frame[..len]);
+
+            buffer.extend(&frame[len..]);
+            assert_eq!(next_command(&mut buffer), Ok(command(&["GET", "task-1"])));
+            assert!(buffer.is_empty());
+        }
+        assert_eq!(next_command(&mut b"PING".to_vec()), Ok(None));
+    }
+
+    #[test]
+    fn rejects_oversized_frames() {
+        let too_many = format!("*{}\r\n", MAX_ARRAY_LEN + 1);
+        assert!(next_command(&mut too_many.into_bytes()).is_err());
+
+        let too_long = format!("*1\r\n${}\r\n", MAX_BULK_LEN + 1);
+        assert!(next_command(&mut too_long.into_bytes()).is_err());
+
+        let mut endless = vec![b'a'; MAX_BULK_LEN + 1];
+        assert!(next_command(&mut endless).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        assert!(next_command(&mut b"*1\r\n:1\r\n".to_vec()).is_err());
+        assert!(next_command(&mut b"*-1\r\n".to_vec()).is_err());
+        assert!(next_command(&mut b"*1\r\n$x\r\n".to_vec()).is_err());
+        assert!(next_command(&mut b"*1\r\n$2\r\n\xff\xfe\r\n".to_vec()).is_err());
+    }
+
+    #[test]
+    fn encodes_the_replies() {
+        let mut out = Vec::new();
+        RespValue::Array(vec![
+            RespValue::ok(),
+            RespValue::error("bad\r\nline"),
+            RespValue::Integer(-2),
+            RespValue::Bulk(Some(String::from("a"))),
+            RespValue::Bulk(None),
+        ])
+        .encode(&mut out);
+        assert_eq!(
+            out,
+            b"*5\r\n+OK\r\n-ERR bad  line\r\n:-2\r\n$1\r\na\r\n$-1\r\n"
+        );
+    }
+}
@@ -0,0 +1,4 @@
+mod codec;
+pub mod server;
+
+pub use server::*;
@@ -0,0 +1,222 @@
+use super::codec::{next_command, RespValue};
+use crate::app::{App, Command, Heap, ReplyStatus, Response, Task};
+use crate::listener;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use spoler_proto::TaskBuilder;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+//Serves a subset of the redis protocol (resp), so redis-cli and the redis client libraries
+//can enqueue and inspect tasks. the commands go through the same permissions as in tcp
+//
+//SCHEDULE queue id [IN seconds | AT eta] [PAYLOAD payload] [URL url] [METHOD method]
+//         [HEADER name:value] [PYTHON executor_ref] [EVERY seconds | CRON expression] [TIMES repetitions]
+//         [PRIORITY priority] [TZ time_zone] [KEY idempotency_key] [UPSERT]
+//SCHEDULE {task json}       enqueues a task, replies with its resolved eta
+//CANCEL id                  cancels a pending task, replies with how many were removed
+//QLEN queue                 how many tasks the queue holds
+//QPEEK [queue]              the next due task as json, in one queue or in all of them, nil when empty
+//AUTH [user] token, PING, ECHO, COMMAND, QUIT
+//
+//with tls enabled the connections are encrypted, as the tcp ones (redis-cli --tls)
+pub async fn serve(app: App<Heap<Task>>, listener: TcpListener, tls_acceptor: Option<TlsAcceptor>) {
+    let lifecycle = app.lifecycle.clone();
+    listener::accept(
+        "Resp",
+        listener,
+        tls_acceptor,
+        lifecycle,
+        move |stream, peer| {
+            println!("Accepted and running incoming resp connection: {}", peer);
+            run(app.clone(), stream)
+        },
+    )
+    .await;
+}
+
+async fn run<S>(mut app: App<Heap<Task>>, mut socket: S)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buffer: Vec<u8> = Vec::new();
+    loop {
+        let args = match next_command(&mut buffer) {
+            Ok(Some(args)) => args,
+            Ok(None) => {
+                //the connection was closed or broken
+                if socket.read_buf(&mut buffer).await.unwrap_or(0) == 0 {
+                    return;
+                }
+                continue;
+            }
+            Err(reason) => {
+                let mut out = Vec::new();
+                RespValue::error(format!("Protocol error: {}", reason)).encode(&mut out);
+                let _ = socket.write_all(&out).await;
+                return;
+            }
+        };
+        //empty inline commands are just newlines
+        if args.is_empty() {
+            continue;
+        }
+
+        let quit = args[0].eq_ignore_ascii_case("quit");
+        let reply = match quit {
+            true => RespValue::ok(),
+            false => execute(&mut app, &args).await,
+        };
+        let mut out = Vec::new();
+        reply.encode(&mut out);
+        if socket.write_all(&out).await.is_err() || quit {
+            return;
+        }
+    }
+}
+
+async fn execute(app: &mut App<Heap<Task>>, args: &[String]) -> RespValue {
+    let name = args[0].to_ascii_uppercase();
+    let args = &args[1..];
+    match (name.as_str(), args) {
+        ("PING", []) => RespValue::Simple(String::from("PONG")),
+        ("PING", [message]) | ("ECHO", [message]) => RespValue::Bulk(Some(message.clone())),
+        //redis-cli asks for the docs of the commands when it connects, there are none
+        ("COMMAND", _) => RespValue::Array(Vec::new()),
+        ("AUTH", [token]) | ("AUTH", [_, token]) => {
+            match app
+                .handle_command(Command::Auth {
+                    token: token.clone(),
+                })
+                .await
+            {
+                Response::Auth(reply) if reply.status == ReplyStatus::Accepted => RespValue::ok(),
+                _ => RespValue::Error(String::from("WRONGPASS invalid token")),
+            }
+        }
+        ("SCHEDULE", [_, ..]) => {
+            let task = match parse_task(args) {
+                Ok(task) => task,
+                Err(reason) => return RespValue::error(reason),
+            };
+            let reply = app.enqueue(task).await;
+            match reply.status {
+                ReplyStatus::Accepted => RespValue::Bulk(reply.eta),
+                _ => to_resp(Response::Task(reply)),
+            }
+        }
+        ("CANCEL", [id]) => match app.handle_command(Command::Cancel { id: id.clone() }).await {
+            Response::Cancel(reply) => RespValue::Integer(reply.removed as i64),
+            response => to_resp(response),
+        },
+        ("QLEN", [queue]) => {
+            let command = Command::List {
+                queue: queue.clone(),
+                offset: 0,
+                limit: 0,
+            };
+            match app.handle_command(command).await {
+                Response::List(reply) => RespValue::Integer(reply.total as i64),
+                response => to_resp(response),
+            }
+        }
+        ("QPEEK", []) | ("QPEEK", [_]) => {
+            let queue = args.first().cloned();
+            match app.handle_command(Command::Peek { queue }).await {
+                Response::Peek(reply) => RespValue::Bulk(
+                    reply
+                        .task
+                        .map(|task| serde_json::to_string(&task).expect("Tasks are serializable")),
+                ),
+                response => to_resp(response),
+            }
+        }
+        ("PING" | "ECHO" | "AUTH" | "SCHEDULE" | "CANCEL" | "QLEN" | "QPEEK", _) => {
+            RespValue::error(format!(
+                "wrong number of arguments for '{}' command",
+                name.to_lowercase()
+            ))
+        }
+        _ => RespValue::error(format!("unknown command '{}'", name.to_lowercase())),
+    }
+}
+
+//the rejections and the failed commands, anything else is not expected here
+fn to_resp(response: Response) -> RespValue {
+    match response {
+        Response::Task(reply) => {
+            RespValue::error(reply.reason.unwrap_or_else(|| String::from("Rejected")))
+        }
+        Response::Error(reply) => RespValue::error(reply.reason),
+        _ => RespValue::error("Unexpected reply"),
+    }
+}
+
+//a single argument is the task as json, as in tcp, otherwise the options build it
+fn parse_task(args: &[String]) -> Result<Task, String> {
+    if let [json] = args {
+        return serde_json::from_str(json).map_err(|e| format!("Invalid task: {}", e));
+    }
+
+    let id = args.get(1).ok_or("The id of the task is missing")?;
+    let mut task = TaskBuilder::new(id.clone()).queue(args[0].clone());
+    let mut method = String::from("POST");
+    let mut url = None;
+    let mut headers = Vec::new();
+
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let option = option.to_ascii_uppercase();
+        //the only option without value
+        if option == "UPSERT" {
+            task = task.upsert();
+            continue;
+        }
+        let value = options
+            .next()
+            .ok_or_else(|| format!("{} needs a value", option))?;
+        match option.as_str() {
+            "IN" => task = task.eta(Utc::now() + Duration::seconds(parse_number(&option, value)?)),
+            //a local eta is in the time zone of the task
+            "AT" => {
+                task = match value.parse::<DateTime<Utc>>() {
+                    Ok(eta) => task.eta(eta),
+                    Err(_) => task.local_eta(
+                        value
+                            .parse::<NaiveDateTime>()
+                            .map_err(|_| format!("Invalid eta: {}", value))?,
+                    ),
+                }
+            }
+            "PAYLOAD" => task = task.payload(value.clone()),
+            "URL" => url = Some(value.clone()),
+            "METHOD" => method = value.clone(),
+            "HEADER" => {
+                let (name, value) = value
+                    .split_once(':')
+                    .ok_or_else(|| format!("{} is not a name:value header", value))?;
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+            "PYTHON" => task = task.python(value.clone()),
+            "EVERY" => task = task.repeat_every(parse_number(&option, value)?),
+            "CRON" => task = task.cron(value.clone()),
+            "TIMES" => task = task.repetitions(parse_number(&option, value)?),
+            "PRIORITY" => task = task.priority(parse_number(&option, value)?),
+            "TZ" => task = task.timezone(value.clone()),
+            "KEY" => task = task.idempotency_key(value.clone()),
+            _ => return Err(format!("Unknown option {}", option)),
+        }
+    }
+    if let Some(url) = url {
+        task = task.request(method, url);
+    }
+    for (name, value) in headers {
+        task = task.header(name, value);
+    }
+    Ok(task.build())
+}
+
+fn parse_number<N: std::str::FromStr>(option: &str, raw: &str) -> Result<N, String> {
+    raw.parse()
+        .map_err(|_| format!("{} is not a valid value for {}", raw, option))
+}
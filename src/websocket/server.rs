@@ -0,0 +1,56 @@
+use crate::app::{App, Heap, Task};
+use crate::listener;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{header, StatusCode};
+
+//Serves the tcp protocol over websockets, for producers that can only speak http.
+//every websocket message is one task or command, and it's answered with one message.
+//
+//the connections authenticate with the auth command as in tcp, or with the
+//"authorization: Bearer {token}" header of the handshake when the client can send it
+pub async fn serve(app: App<Heap<Task>>, listener: TcpListener, tls_acceptor: Option<TlsAcceptor>) {
+    let lifecycle = app.lifecycle.clone();
+    listener::accept(
+        "Websocket",
+        listener,
+        tls_acceptor,
+        lifecycle,
+        move |stream, peer| accept(app.clone(), stream, peer.to_string()),
+    )
+    .await;
+}
+
+//makes the websocket handshake and serves the connection,
+//the error response of the handshake is the type tungstenite asks for
+#[allow(clippy::result_large_err)]
+async fn accept<S>(mut app: App<Heap<Task>>, stream: S, peer: String)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let handshake = tokio_tungstenite::accept_hdr_async(
+        stream,
+        |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            let token = request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "));
+            match token {
+                Some(token) if !app.authenticate(token) => {
+                    let mut error = ErrorResponse::new(Some(String::from("Invalid token")));
+                    *error.status_mut() = StatusCode::UNAUTHORIZED;
+                    Err(error)
+                }
+                _ => Ok(response),
+            }
+        },
+    )
+    .await;
+    match handshake {
+        Ok(socket) => app.run_websocket(socket, peer).await,
+        Err(e) => eprintln!("Websocket handshake with {} failed: {}", peer, e),
+    }
+}
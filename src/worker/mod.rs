@@ -1,3 +1,6 @@
+pub mod remote;
+#[allow(clippy::module_inception)]
 pub mod worker;
 
+pub use remote::*;
 pub use worker::*;
@@ -0,0 +1,89 @@
+use crate::worker::AsyncWorker;
+use spoler_client::Client;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::mpsc::channel;
+
+//runs the tasks of remote queues in another host, leasing them from the server.
+//the tasks that finish are acked, the failed ones are nacked and recorded as failed,
+//as the local worker does
+pub struct RemoteWorker {
+    pub client: Client,
+    pub queues: Vec<String>,
+    //how many tasks run at the same time
+    pub concurrency: usize,
+    pub visibility_timeout: u32,
+    //how long to wait between leases when the queues are empty
+    pub poll_interval: Duration,
+}
+
+impl RemoteWorker {
+    //runs until ctrl-c, then waits for the running tasks
+    pub async fn run(mut self) -> Result<(), String> {
+        //the lease ids of the finished tasks, with their result
+        let (done_sender, mut done) = channel::<(String, Result<(), String>)>(self.concurrency);
+        let mut running: HashSet<String> = HashSet::new();
+        let mut poll = tokio::time::interval(self.poll_interval);
+        //heartbeats keep the leases alive while the tasks run
+        let mut heartbeat =
+            tokio::time::interval(Duration::from_secs((self.visibility_timeout / 2).into()));
+        let shutdown = tokio::signal::ctrl_c();
+        tokio::pin!(shutdown);
+        let mut stopping = false;
+
+        println!("Remote worker leasing from queues {:?}", self.queues);
+        loop {
+            if stopping && running.is_empty() {
+                return Ok(());
+            }
+            tokio::select! {
+                _ = &mut shutdown, if !stopping => {
+                    println!("Stopping, waiting for {} running tasks", running.len());
+                    stopping = true;
+                }
+                Some((lease_id, result)) = done.recv() => {
+                    running.remove(&lease_id);
+                    let reply = match result {
+                        Ok(()) => self.client.ack(&lease_id).await,
+                        Err(e) => self.client.nack(&lease_id, Some(e), false, 0).await,
+                    };
+                    if let Err(e) = reply {
+                        eprintln!("Failed to finish the lease {}: {}", lease_id, e);
+                    }
+                }
+                _ = heartbeat.tick(), if !running.is_empty() => {
+                    let lease_ids = running.iter().cloned().collect();
+                    if let Err(e) = self.client.heartbeat(lease_ids, self.visibility_timeout).await {
+                        eprintln!("Failed to send the heartbeat: {}", e);
+                    }
+                }
+                _ = poll.tick(), if !stopping && running.len() < self.concurrency => {
+                    let max = self.concurrency - running.len();
+                    let leased = match self.client.lease(&self.queues, max, self.visibility_timeout).await {
+                        Ok(leased) => leased,
+                        Err(e) => {
+                            eprintln!("Failed to lease tasks: {}", e);
+                            continue;
+                        }
+                    };
+                    for leased in leased {
+                        println!("Remote worker: got incoming task");
+                        running.insert(leased.lease_id.clone());
+                        let done_sender = done_sender.clone();
+                        tokio::spawn(async move {
+                            //the task runs apart, so a panic nacks it instead of keeping its lease forever
+                            let task = leased.task;
+                            let processing = tokio::spawn(async move {
+                                AsyncWorker::process_task(&task).await
+                            });
+                            let result = processing
+                                .await
+                                .unwrap_or_else(|e| Err(format!("The task panicked: {}", e)));
+                            let _ = done_sender.send((leased.lease_id, result)).await;
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
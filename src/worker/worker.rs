@@ -1,49 +1,60 @@
+use crate::app::{Lifecycle, StatusRegistry};
 use crate::utils;
 use crate::Task;
 use pyo3::prelude::*;
 use reqwest::header::HeaderMap;
 use reqwest::Method;
 use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
 use tokio::sync::mpsc::Receiver;
 
+#[allow(dead_code)]
 pub trait Worker {
     fn new() -> Self;
-    fn start(&self) -> ();
+    fn start(&self);
 }
 
 pub struct AsyncWorker {}
 
 impl AsyncWorker {
     // Entrypoint for the worker processes
-    pub fn run(self, receiver: Receiver<Task>, app_settings: HashMap<String, String>) -> () {
-        //is this application a python app ?
-        let app =
-            utils::get_string_from_settings(&app_settings, "--app".to_string(), "".to_string());
-
-        match app.as_str() {
-            "python" => {
-                Self::_run_python(receiver, &app_settings);
+    // with the path of a python project, the tasks run in that python app
+    pub fn run(
+        self,
+        receiver: Receiver<Task>,
+        python_project_path: Option<String>,
+        statuses: StatusRegistry,
+        lifecycle: Lifecycle,
+    ) {
+        match python_project_path {
+            Some(python_project_path) => {
+                //the python worker blocks this thread waiting for the tasks
+                tokio::task::block_in_place(|| {
+                    Self::_run_python(receiver, python_project_path, statuses, lifecycle)
+                });
             }
-            _ => {
-                tokio::spawn(async move {
-                    self._run(receiver, &app_settings).await;
+            None => {
+                //block this thread until the worker finishes, as the python worker does
+                tokio::task::block_in_place(|| {
+                    tokio::runtime::Handle::current()
+                        .block_on(self._run(receiver, statuses, lifecycle))
                 });
             }
         }
     }
 
     /// Starts the worker process for python applications
-    fn _run_python(mut receiver: Receiver<Task>, app_settings: &HashMap<String, String>) -> () {
+    fn _run_python(
+        mut receiver: Receiver<Task>,
+        python_project_path: String,
+        statuses: StatusRegistry,
+        lifecycle: Lifecycle,
+    ) {
         eprintln!("Starting execution of python application worker");
         pyo3::prepare_freethreaded_python();
         let python_guard = Python::acquire_gil();
         let python = python_guard.python();
         //get the main app
-        let python_project_path = utils::get_string_from_settings(
-            app_settings,
-            "--project-path".to_string(),
-            "".to_string(),
-        );
         let code = utils::get_code_from_file(python_project_path.clone());
 
         let main_app =
@@ -60,17 +71,41 @@ impl AsyncWorker {
             .call0()
             .expect("Error initializing the python application");
 
+        let runtime = tokio::runtime::Handle::current();
         loop {
-            let message = receiver.try_recv();
+            //wait for the next task, the worker stops when the app is gone
+            let message = runtime.block_on(receiver.recv());
             match message {
-                Err(_) => continue,
-                Ok(task) => {
+                None => break,
+                Some(task) => {
                     eprintln!("Python Worker: got incoming task");
+                    //a draining node keeps the task for the snapshot
+                    if !lifecycle.task_received(&task) {
+                        continue;
+                    }
 
                     //since we have only one python thread, we are going to run each task in sync way,
-                    //TODO: handle edge cases, and don't fail on error
-                    let python_fn_name = task.settings.unwrap().executor_ref.unwrap();
-                    main_app.call_method0(&python_fn_name).expect("");
+                    //errors are recorded in the task status instead of stopping the worker
+                    statuses.running(&task);
+                    let python_fn_name = task
+                        .settings
+                        .as_ref()
+                        .and_then(|settings| settings.executor_ref.clone());
+                    let call = || match python_fn_name {
+                        Some(python_fn_name) => main_app
+                            .call_method0(python_fn_name.as_str())
+                            .map(|_| ())
+                            .map_err(|e| e.to_string()),
+                        None => Err(String::from("Python tasks must contain an executor_ref")),
+                    };
+                    //a panic fails the task too, the worker keeps running and the drain does not wait for it
+                    let result = std::panic::catch_unwind(AssertUnwindSafe(call))
+                        .unwrap_or_else(|_| Err(String::from("The task panicked")));
+                    match result {
+                        Ok(()) => statuses.succeeded(&task),
+                        Err(e) => statuses.failed(&task, e),
+                    }
+                    lifecycle.task_finished();
                 }
             }
         }
@@ -78,26 +113,48 @@ impl AsyncWorker {
     }
 
     //run a normal app (requests, tcp tasks)
-    async fn _run(self, mut receiver: Receiver<Task>, _: &HashMap<String, String>) -> () {
+    async fn _run(
+        self,
+        mut receiver: Receiver<Task>,
+        statuses: StatusRegistry,
+        lifecycle: Lifecycle,
+    ) {
         loop {
             let message = receiver.recv().await;
-            match message {
-                Some(task) => {
-                    println!("Worker: got incoming task");
-
-                    //now process the task, the task should have enought information for knowing how it needs to be processed
-                    //and the worker should follow that guidelines;
-                    tokio::task::spawn(async move {
-                        let _ = AsyncWorker::process_task(task).await;
-                    });
+            if let Some(task) = message {
+                println!("Worker: got incoming task");
+                //a draining node keeps the task for the snapshot
+                if !lifecycle.task_received(&task) {
+                    continue;
                 }
-                None => (),
+
+                //now process the task, the task should have enought information for knowing how it needs to be processed
+                //and the worker should follow that guidelines;
+                let statuses = statuses.clone();
+                let lifecycle = lifecycle.clone();
+                tokio::task::spawn(async move {
+                    statuses.running(&task);
+                    //the task runs apart, so a panic fails it instead of leaving it running forever
+                    let running = task.clone();
+                    let processing =
+                        tokio::task::spawn(
+                            async move { AsyncWorker::process_task(&running).await },
+                        );
+                    let result = processing
+                        .await
+                        .unwrap_or_else(|e| Err(format!("The task panicked: {}", e)));
+                    match result {
+                        Ok(()) => statuses.succeeded(&task),
+                        Err(e) => statuses.failed(&task, e),
+                    }
+                    lifecycle.task_finished();
+                });
             }
         }
     }
 
     //to do, do this asynchronously ?
-    pub async fn process_task(task: Task) -> Result<(), String> {
+    pub async fn process_task(task: &Task) -> Result<(), String> {
         match task.task_type {
             /*TaskType::Api*/
             1 => Self::process_request_task(task).await,
@@ -112,7 +169,7 @@ impl AsyncWorker {
 
     //Process a request task
     //A request Task is a task that needs to be resolved calling an external api
-    async fn process_request_task(task: Task) -> Result<(), String> {
+    async fn process_request_task(task: &Task) -> Result<(), String> {
         // we have all the data, now, we need to make the request
         // use reqwest as a library for that .
         if task.settings.is_none() {
@@ -121,17 +178,15 @@ impl AsyncWorker {
         let task_settings = task.settings.as_ref().unwrap();
 
         //get the headers
-        let cloned_headers = task_settings.headers.clone().unwrap_or(String::from("{}"));
-        let headers: HashMap<&str, &str> = serde_json::from_str(cloned_headers.as_str()).unwrap();
-        let headers = get_headers(headers);
-        let method = task_settings.method.clone().unwrap_or(String::new());
-        let url = task_settings.url.clone().unwrap_or(String::new());
+        let headers = get_headers(task_settings.get_headers()?)?;
+        let method = task_settings.method.clone().unwrap_or_default();
+        let url = task_settings.url.clone().unwrap_or_default();
 
         //TODO: use a more low-level library like hyper for example for this
         match reqwest::Client::new()
             .request(get_method(method), url)
             .headers(headers)
-            .json(&task.payload.unwrap())
+            .json(&task.payload)
             .send()
             .await
         {
@@ -141,20 +196,28 @@ impl AsyncWorker {
     }
 }
 
-fn get_headers(headers: HashMap<&str, &str>) -> HeaderMap {
+fn get_headers(headers: HashMap<String, String>) -> Result<HeaderMap, String> {
     headers
         .into_iter()
-        .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
-        .collect::<HeaderMap>()
+        .map(|(name, value)| {
+            Ok((
+                name.parse()
+                    .map_err(|_| format!("Invalid header name {}", name))?,
+                value
+                    .parse()
+                    .map_err(|_| format!("Invalid value of the header {}", name))?,
+            ))
+        })
+        .collect()
 }
 
 fn get_method(method: String) -> Method {
-    return match method.as_str() {
+    match method.as_str() {
         "GET" => Method::GET,
         "POST" => Method::POST,
         "DELETE" => Method::DELETE,
         "PUT" => Method::PUT,
         "PATCH" => Method::PATCH,
         _ => Method::OPTIONS,
-    };
+    }
 }